@@ -0,0 +1,206 @@
+use std::{marker::PhantomData, ptr::NonNull};
+
+use crate::{Reading, Strong, Weak};
+
+const UNSHARED: &str = "linked list node locked outside of its list";
+
+struct Node<T>
+{
+    value: T,
+    prev: Option<Weak<Node<T>>>,
+    next: Option<Strong<Node<T>>>,
+}
+
+/// Doubly-linked list whose nodes own their successor and hold a weak
+/// reference to their predecessor, so back-links to a removed node are
+/// invalidated rather than left dangling.
+pub struct LinkedList<T>
+{
+    head: Option<Strong<Node<T>>>,
+    tail: Option<Weak<Node<T>>>,
+    len: usize,
+}
+
+impl<T> LinkedList<T>
+{
+    pub fn new() -> Self
+    {
+        Self {
+            head: None,
+            tail: None,
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize { self.len }
+
+    pub fn is_empty(&self) -> bool { self.len == 0 }
+
+    pub fn push_front(&mut self, value: T)
+    {
+        let next = self.head.take();
+        let node = Strong::new(Node {
+            value,
+            prev: None,
+            next: None,
+        });
+        match &next {
+            Some(next) => next.try_write().expect(UNSHARED).prev = Some(node.alias()),
+            None => self.tail = Some(node.alias()),
+        }
+        node.try_write().expect(UNSHARED).next = next;
+        self.head = Some(node);
+        self.len += 1;
+    }
+
+    pub fn push_back(&mut self, value: T)
+    {
+        let prev = self.tail.take();
+        let node = Strong::new(Node {
+            value,
+            prev: prev.clone(),
+            next: None,
+        });
+        self.tail = Some(node.alias());
+        match prev {
+            Some(prev) => prev.try_write().expect(UNSHARED).next = Some(node),
+            None => self.head = Some(node),
+        }
+        self.len += 1;
+    }
+
+    pub fn pop_front(&mut self) -> Option<T>
+    {
+        let head = self.head.take()?;
+        let Node { value, next, .. } = *head.try_take().ok().expect(UNSHARED);
+        match &next {
+            Some(next) => next.try_write().expect(UNSHARED).prev = None,
+            None => self.tail = None,
+        }
+        self.head = next;
+        self.len -= 1;
+        Some(value)
+    }
+
+    pub fn pop_back(&mut self) -> Option<T>
+    {
+        let tail = self.tail.take()?;
+        let prev = tail.try_read().expect(UNSHARED).prev.clone();
+        let owner = match &prev {
+            Some(prev) => prev.try_write().expect(UNSHARED).next.take(),
+            None => self.head.take(),
+        };
+        self.tail = prev;
+        self.len -= 1;
+        Some(owner?.try_take().ok().expect(UNSHARED).value)
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<T>
+    {
+        if index >= self.len {
+            return None;
+        } else if index == 0 {
+            return self.pop_front();
+        }
+        let mut prev = self.head.as_ref()?.alias();
+        for _ in 1..index {
+            let next = prev.try_read().expect(UNSHARED).next.as_ref()?.alias();
+            prev = next;
+        }
+        let owner = prev.try_write().expect(UNSHARED).next.take()?;
+        let Node { value, next, .. } = *owner.try_take().ok().expect(UNSHARED);
+        match &next {
+            Some(next) => next.try_write().expect(UNSHARED).prev = Some(prev.clone()),
+            None => self.tail = Some(prev.clone()),
+        }
+        prev.try_write().expect(UNSHARED).next = next;
+        self.len -= 1;
+        Some(value)
+    }
+
+    pub fn iter(&self) -> Iter<'_, T>
+    {
+        Iter {
+            next: self.head.as_ref().map(Strong::alias),
+            list: PhantomData,
+        }
+    }
+}
+
+impl<T> Default for LinkedList<T>
+{
+    fn default() -> Self { Self::new() }
+}
+
+impl<T> Drop for LinkedList<T>
+{
+    fn drop(&mut self) { while self.pop_front().is_some() {} }
+}
+
+pub struct Iter<'a, T>
+{
+    next: Option<Weak<Node<T>>>,
+    list: PhantomData<&'a LinkedList<T>>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T>
+{
+    type Item = Reading<'a, T>;
+
+    fn next(&mut self) -> Option<Self::Item>
+    {
+        let node = self.next.take()?;
+        self.next = node
+            .try_read()
+            .expect(UNSHARED)
+            .next
+            .as_ref()
+            .map(Strong::alias);
+        Reading::try_new(node.0.map(|n| NonNull::from(unsafe { &n.as_ref().value })))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn contents(list: &LinkedList<i32>) -> Vec<i32> { list.iter().map(|x| *x).collect() }
+
+    #[test]
+    fn pushes_and_pops_at_both_ends()
+    {
+        let mut list = LinkedList::new();
+        list.push_back(2);
+        list.push_front(1);
+        list.push_back(3);
+        assert_eq!(contents(&list), [1, 2, 3]);
+        assert_eq!(list.pop_back(), Some(3));
+        assert_eq!(list.pop_front(), Some(1));
+        assert_eq!(list.pop_front(), Some(2));
+        assert!(list.is_empty());
+        assert_eq!(list.pop_back(), None);
+    }
+
+    #[test]
+    fn removing_a_middle_node_invalidates_back_links_to_it()
+    {
+        let mut list = LinkedList::new();
+        for x in 0..3 {
+            list.push_back(x);
+        }
+        let head = list.head.as_ref().unwrap().alias();
+        let middle = head.try_read().unwrap().next.as_ref().unwrap().alias();
+        let tail = list.tail.clone().unwrap();
+        let stale = tail.try_read().unwrap().prev.clone().unwrap();
+        assert!(stale.ptr_eq(&middle));
+
+        assert_eq!(list.remove(1), Some(1));
+        assert!(!middle.is_valid());
+        assert!(!stale.is_valid());
+        let prev = tail.try_read().unwrap().prev.clone().unwrap();
+        assert!(prev.ptr_eq(&head));
+        assert_eq!(contents(&list), [0, 2]);
+        assert_eq!(list.len(), 2);
+    }
+}
@@ -0,0 +1,27 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static REENTRANT_PANIC: AtomicBool = AtomicBool::new(false);
+static LOCK_ORDER_CHECK: AtomicBool = AtomicBool::new(false);
+
+/// In debug builds, panic instead of failing when a thread tries to take a
+/// second exclusive lock on a thread-local account it already holds
+/// exclusively. Fallible wrappers that lock internally then panic too, so
+/// this is meant for hunting down self-deadlocks. Disabled by default; has no
+/// effect in release builds.
+pub fn reentrant_panic(enabled: bool) { REENTRANT_PANIC.store(enabled, Ordering::Relaxed) }
+
+pub(crate) fn reentrant_panic_enabled() -> bool
+{
+    cfg!(debug_assertions) && REENTRANT_PANIC.load(Ordering::Relaxed)
+}
+
+/// In debug builds, panic when a thread takes an exclusive lock whose
+/// [`Strong::lock_order_key`](crate::Strong::lock_order_key) is not greater
+/// than that of every exclusive lock it already holds. Disabled by default;
+/// has no effect in release builds.
+pub fn lock_order_check(enabled: bool) { LOCK_ORDER_CHECK.store(enabled, Ordering::Relaxed) }
+
+pub(crate) fn lock_order_check_enabled() -> bool
+{
+    cfg!(debug_assertions) && LOCK_ORDER_CHECK.load(Ordering::Relaxed)
+}
@@ -0,0 +1,98 @@
+use std::{
+    cell::RefCell,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use lazy_static::lazy_static;
+
+use crate::tracking::{self, AccountEnum, Tracking};
+
+struct Deferred
+{
+    account: AccountEnum,
+    locking_primitive: fn(&AccountEnum) -> bool,
+    consume: Box<dyn FnOnce()>,
+}
+
+// Only drops of `SENDABLE` owners are queued globally, and always with their
+// account resolved to the global one, so whichever thread purges them touches
+// neither another thread's ledger nor a referent bound to its thread.
+unsafe impl Send for Deferred {}
+
+thread_local! {
+    static DROP_QUEUE : RefCell<Vec<Deferred>> = const { RefCell::new(Vec::new()) };
+}
+
+lazy_static! {
+    static ref GLOBAL_DROP_QUEUE: parking_lot::Mutex<Vec<Deferred>> =
+        parking_lot::Mutex::new(Vec::new());
+}
+
+// entries in the global queue, so that purging can skip taking its lock
+static GLOBAL_PENDING: AtomicUsize = AtomicUsize::new(0);
+
+/// assumes ownership of the account
+///
+/// The closure only runs once the account can be locked, i.e. after every
+/// guard borrowing the referent has been released, so erasing its lifetime
+/// does not let it outlive what it captures. Owners whose referent is `Send`
+/// are queued process-wide, so that whichever thread releases the last guard
+/// runs it; any other owner's drop stays with the thread that owns it.
+pub(crate) unsafe fn defer<'a>(
+    account: AccountEnum, locking_primitive: fn(&AccountEnum) -> bool,
+    consume: Box<dyn FnOnce() + 'a>,
+)
+{
+    let consume = std::mem::transmute::<Box<dyn FnOnce() + 'a>, Box<dyn FnOnce()>>(consume);
+    let sendable = account.flags() & tracking::SENDABLE != 0;
+    if let (true, global @ AccountEnum::Global(_)) = (sendable, account.resolved()) {
+        GLOBAL_DROP_QUEUE.lock().push(Deferred {
+            account: global,
+            locking_primitive,
+            consume,
+        });
+        GLOBAL_PENDING.fetch_add(1, Ordering::SeqCst);
+        // the last guard may have been released on another thread after the
+        // account failed to lock but before it was queued here
+        purge();
+    } else {
+        DROP_QUEUE.with_borrow_mut(|queue| {
+            queue.push(Deferred {
+                account,
+                locking_primitive,
+                consume,
+            })
+        })
+    }
+}
+
+pub(crate) fn purge() -> usize
+{
+    let mut ready = DROP_QUEUE.with_borrow_mut(lockable);
+    if GLOBAL_PENDING.load(Ordering::SeqCst) != 0 {
+        let global = lockable(&mut GLOBAL_DROP_QUEUE.lock());
+        GLOBAL_PENDING.fetch_sub(global.len(), Ordering::SeqCst);
+        ready.extend(global);
+    }
+    let purged = ready.len();
+    for deferred in ready {
+        unsafe {
+            tracking::free(deferred.account);
+        }
+        (deferred.consume)();
+    }
+    purged
+}
+
+// takes out the entries whose account could be locked, leaving the rest
+fn lockable(queue: &mut Vec<Deferred>) -> Vec<Deferred>
+{
+    if queue.is_empty() {
+        return Vec::new();
+    }
+    let (ready, remaining) = std::mem::take(queue)
+        .into_iter()
+        .partition(|deferred| (deferred.locking_primitive)(&deferred.account));
+    *queue = remaining;
+    ready
+}
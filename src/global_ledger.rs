@@ -1,6 +1,9 @@
 use lazy_static::lazy_static;
-use lock_api::{RawRwLock, RawRwLockUpgrade};
-use std::sync::atomic::{AtomicU64, Ordering};
+use lock_api::{RawRwLock, RawRwLockDowngrade, RawRwLockTimed, RawRwLockUpgrade};
+use std::{
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    time::Instant,
+};
 
 use super::*;
 use crate::tracking::Tracking;
@@ -9,6 +12,21 @@ use crate::tracking::Tracking;
 #[derive(Debug, Clone, Copy)]
 pub(crate) struct GlobalIndex(&'static GlobalAccount);
 
+impl GlobalIndex
+{
+    pub(crate) fn address(&self) -> usize { self.0 as *const GlobalAccount as usize }
+
+    /// never allocated or invalidated, so no reference is ever valid against it
+    pub(crate) const fn dead() -> Self { GlobalIndex(&DEAD) }
+}
+
+static DEAD: GlobalAccount = GlobalAccount {
+    lock: parking_lot::RawRwLock::INIT,
+    generation: AtomicU64::new(0),
+    allocation_id: AtomicU64::new(0),
+    published: AtomicU64::new(0),
+};
+
 impl Tracking for GlobalIndex
 {
     fn generation(&self) -> u64 { self.0.generation() }
@@ -16,15 +34,33 @@ impl Tracking for GlobalIndex
     fn try_lock_exclusive(&self) -> bool { self.0.try_lock_exclusive() }
     fn lock_exclusive(&self) { self.0.lock_exclusive() }
     fn try_lock_shared(&self) -> bool { self.0.try_lock_shared() }
+    fn lock_shared(&self) { self.0.lock_shared() }
+    fn try_lock_exclusive_until(&self, deadline: Instant) -> bool
+    {
+        self.0.try_lock_exclusive_until(deadline)
+    }
+    fn try_lock_shared_until(&self, deadline: Instant) -> bool
+    {
+        self.0.try_lock_shared_until(deadline)
+    }
     fn try_upgrade(&self) -> bool { self.0.try_upgrade() }
+    unsafe fn downgrade(&self) { self.0.downgrade() }
     unsafe fn unlock_exclusive(&self) { self.0.unlock_exclusive() }
     unsafe fn unlock_shared(&self) { self.0.unlock_shared() }
+    fn flags(&self) -> u64 { self.0.flags() }
+    fn raise_flags(&self, flags: u64) { self.0.raise_flags(flags) }
+    fn allocation_id(&self) -> u64 { self.0.allocation_id() }
+    fn set_allocation_id(&self, id: u64) { self.0.set_allocation_id(id) }
+    fn publish(&self) -> u64 { self.0.publish() }
+    fn published(&self) -> u64 { self.0.published() }
 }
 
 struct GlobalAccount
 {
     lock: parking_lot::RawRwLock,
     generation: AtomicU64,
+    allocation_id: AtomicU64,
+    published: AtomicU64,
 }
 
 impl std::fmt::Debug for GlobalAccount
@@ -44,14 +80,53 @@ impl Tracking for GlobalAccount
         self.generation.load(Ordering::Relaxed) & RawRef::<()>::COUNTER_MASK
     }
 
-    fn invalidate(&self) -> u64 { self.generation.fetch_add(1, Ordering::Relaxed) }
+    fn invalidate(&self) -> u64
+    {
+        let current = self.generation();
+        self.generation.store(current + 1, Ordering::Relaxed);
+        current
+    }
 
     fn try_lock_exclusive(&self) -> bool { self.lock.try_lock_exclusive() }
 
-    fn lock_exclusive(&self) { self.lock.lock_exclusive() }
+    fn lock_exclusive(&self)
+    {
+        if !self.lock.try_lock_exclusive() {
+            wait(&WRITERS_WAITING, || {
+                self.lock.lock_exclusive();
+                true
+            });
+        }
+    }
 
     fn try_lock_shared(&self) -> bool { self.lock.try_lock_shared() }
 
+    fn lock_shared(&self)
+    {
+        if !self.lock.try_lock_shared() {
+            wait(&READERS_WAITING, || {
+                self.lock.lock_shared();
+                true
+            });
+        }
+    }
+
+    fn try_lock_exclusive_until(&self, deadline: Instant) -> bool
+    {
+        self.lock.try_lock_exclusive()
+            || wait(&WRITERS_WAITING, || {
+                self.lock.try_lock_exclusive_until(deadline)
+            })
+    }
+
+    fn try_lock_shared_until(&self, deadline: Instant) -> bool
+    {
+        self.lock.try_lock_shared()
+            || wait(&READERS_WAITING, || {
+                self.lock.try_lock_shared_until(deadline)
+            })
+    }
+
     fn try_upgrade(&self) -> bool
     {
         if self.lock.try_lock_upgradable() {
@@ -68,21 +143,84 @@ impl Tracking for GlobalAccount
                 self.lock.unlock_upgradable();
             }
         }
-        return false;
+        false
     }
 
+    unsafe fn downgrade(&self) { self.lock.downgrade() }
+
     unsafe fn unlock_exclusive(&self) { self.lock.unlock_exclusive() }
 
     unsafe fn unlock_shared(&self) { self.lock.unlock_shared() }
+
+    fn flags(&self) -> u64 { self.generation.load(Ordering::Relaxed) & !RawRef::<()>::COUNTER_MASK }
+
+    fn raise_flags(&self, flags: u64)
+    {
+        self.generation
+            .fetch_or(flags & !RawRef::<()>::COUNTER_MASK, Ordering::Relaxed);
+    }
+
+    fn allocation_id(&self) -> u64 { self.allocation_id.load(Ordering::Relaxed) }
+
+    fn set_allocation_id(&self, id: u64) { self.allocation_id.store(id, Ordering::Relaxed) }
+
+    // the release/acquire pair that publishing hangs off: a consumer whose
+    // load reads this increment, or a later one, sees the producer's writes
+    fn publish(&self) -> u64 { self.published.fetch_add(1, Ordering::Release) + 1 }
+
+    fn published(&self) -> u64 { self.published.load(Ordering::Acquire) }
+}
+
+static READERS_WAITING: AtomicUsize = AtomicUsize::new(0);
+static WRITERS_WAITING: AtomicUsize = AtomicUsize::new(0);
+
+// tallies a thread as waiting for as long as the slow path blocks
+fn wait<F: FnOnce() -> bool>(tally: &AtomicUsize, slow_path: F) -> bool
+{
+    tally.fetch_add(1, Ordering::Relaxed);
+    let res = slow_path();
+    tally.fetch_sub(1, Ordering::Relaxed);
+    res
+}
+
+pub(crate) fn waiter_stats() -> WaiterStats
+{
+    WaiterStats {
+        readers_waiting: READERS_WAITING.load(Ordering::Relaxed),
+        writers_waiting: WRITERS_WAITING.load(Ordering::Relaxed),
+    }
 }
 
 pub(crate) fn allocate() -> GlobalIndex { recycle().unwrap_or_else(fresh) }
 
+/// allocates an account whose generation word, flags included, carries on
+/// from that of a thread-local account, so that its references stay valid
+///
+/// Recycled accounts only qualify if their generation is not ahead, as it
+/// must never go back. The publication count carries on likewise.
+pub(crate) fn allocate_continuing(generation: u64, published: u64) -> GlobalIndex
+{
+    let counter = generation & RawRef::<()>::COUNTER_MASK;
+    let recycled = {
+        let mut free_list = FREE_LIST.write();
+        match free_list.last() {
+            Some(gi) if gi.generation() <= counter => free_list.pop(),
+            _ => None,
+        }
+    };
+    let res = recycled.unwrap_or_else(fresh);
+    res.0.generation.store(generation, Ordering::Relaxed);
+    res.0.published.store(published, Ordering::Relaxed);
+    res
+}
+
 fn fresh() -> GlobalIndex
 {
     GlobalIndex(Box::leak(Box::new(GlobalAccount {
         lock: parking_lot::RawRwLock::INIT,
         generation: AtomicU64::new(RawRef::<()>::COUNTER_INIT),
+        allocation_id: AtomicU64::new(0),
+        published: AtomicU64::new(0),
     })) as &_)
 }
 
@@ -98,5 +236,7 @@ pub(crate) unsafe fn free(gi: GlobalIndex)
 {
     gi.invalidate();
     gi.unlock_exclusive();
-    FREE_LIST.write().push(gi)
+    if gi.generation() != RawRef::<()>::COUNTER_MASK {
+        FREE_LIST.write().push(gi)
+    }
 }
@@ -1,26 +1,289 @@
-#![feature(local_key_cell_methods, assert_matches)]
+#![feature(coerce_unsized, unsize)]
 #![allow(unused)]
 
+pub mod collections;
+pub mod config;
+mod drop_queue;
 mod global_ledger;
 mod local_ledger;
 mod raw_ref;
+#[cfg(feature = "serde")]
+pub mod serde;
+pub mod testing;
+#[cfg(test)]
+mod tests;
 mod tracking;
 
 use std::{
-    assert_matches::assert_matches,
+    assert_matches,
+    cell::{Cell, OnceCell},
     io::Read,
-    marker::PhantomData,
-    ops::{Deref, DerefMut},
+    marker::{PhantomData, Unsize},
+    ops::{CoerceUnsized, Deref, DerefMut, Range},
+    pin::Pin,
     ptr::NonNull,
+    sync::mpsc,
+    time::{Duration, Instant},
 };
 
 use raw_ref::*;
 use tracking::{AccountEnum, Tracking};
 
+/// Usage of this thread's local account ledger. Accounts whose generation
+/// counter is exhausted are retired instead of returning to the free list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CounterEfficiency
+{
+    pub allocated: usize,
+    pub free_listed: usize,
+    pub live: usize,
+    pub retired: usize,
+}
+
+pub fn counter_efficiency() -> CounterEfficiency { local_ledger::efficiency() }
+
+/// Runs `f` against a fresh local account ledger, restoring this thread's
+/// ledger and its statistics afterwards. Accounts allocated inside are never
+/// reused outside, nor the other way around.
+pub fn with_isolated_pool<F: FnOnce() -> R, R>(f: F) -> R { local_ledger::with_isolated_pool(f) }
+
+/// Threads currently blocked waiting for a globalized account's lock, across
+/// all accounts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WaiterStats
+{
+    pub readers_waiting: usize,
+    pub writers_waiting: usize,
+}
+
+pub fn waiter_stats() -> WaiterStats { global_ledger::waiter_stats() }
+
+/// Types with the exact layout of `T`, such as `#[repr(transparent)]`
+/// newtypes around it.
+///
+/// # Safety
+///
+/// `Self` must have the same size, alignment and validity invariants as `T`,
+/// so that a `Box<T>` can be reinterpreted as a `Box<Self>`.
+pub unsafe trait TransparentWrapper<T> {}
+
+/// Reclaims allocations whose `Strong` was dropped while they were borrowed
+/// on this thread, if those borrows have since ended. Returns how many were
+/// reclaimed.
+pub fn sweep() -> usize { drop_queue::purge() }
+
+/// Write-locks two strong references of possibly different types, in
+/// [`Strong::lock_order_key`] order. Returns `None` if they share an account or
+/// if either lock fails, in which case nothing stays locked.
+pub fn write_pair_mut<'a, A, B>(
+    a: &'a Strong<A>, b: &'a Strong<B>,
+) -> Option<(Writing<'a, A>, Writing<'a, B>)>
+{
+    let (a_key, b_key) = (a.lock_order_key(), b.lock_order_key());
+    if a_key == b_key {
+        None
+    } else if a_key < b_key {
+        let wa = a.try_write()?;
+        Some((wa, b.try_write()?))
+    } else {
+        let wb = b.try_write()?;
+        Some((a.try_write()?, wb))
+    }
+}
+
+/// Runs `body` with a [`Transaction`] through which it locks any number of
+/// strong references. Every lock is released when the body returns, and the
+/// borrows it hands out cannot escape the body.
+///
+/// Locks are only ever tried, never waited on, so transactions cannot
+/// deadlock; a contended lock makes its method return `None`, which the body
+/// can propagate with `?` to abort.
+pub fn transaction<'env, F, R>(body: F) -> Option<R>
+where
+    F: for<'tx> FnOnce(&mut Transaction<'tx, 'env>) -> Option<R>,
+{
+    body(&mut Transaction {
+        held: Vec::new(),
+        scope: PhantomData,
+        env: PhantomData,
+    })
+}
+
+pub struct Transaction<'tx, 'env: 'tx>
+{
+    held: Vec<(AccountEnum, bool)>,
+    scope: PhantomData<&'tx mut &'tx ()>,
+    env: PhantomData<&'env mut &'env ()>,
+}
+
+impl<'tx, 'env> Transaction<'tx, 'env>
+{
+    pub fn read<T>(&mut self, strong: &'env Strong<T>) -> Option<&'tx T>
+    {
+        strong.invariant();
+        let account = strong.0.account();
+        if !account.try_lock_shared() {
+            return None;
+        }
+        self.held.push((account, false));
+        Some(unsafe { strong.0.pointer().as_ptr().as_ref() })
+    }
+
+    /// Fails on a reference this transaction already holds, so two borrows of
+    /// one referent never coexist. Subject to [`config::lock_order_check`]
+    /// like [`Strong::try_write`].
+    pub fn write<T>(&mut self, strong: &'env Strong<T>) -> Option<&'tx mut T>
+    {
+        strong.invariant();
+        let account = strong.0.account();
+        if !account.try_lock_exclusive() {
+            return None;
+        }
+        Writing::<T>::check_order(&account);
+        self.held.push((account, true));
+        if account.flags() & tracking::PINNED != 0 {
+            panic!("{}", PINNED)
+        }
+        Some(unsafe { strong.0.pointer().as_ptr().as_mut() })
+    }
+}
+
+impl<'tx, 'env> Drop for Transaction<'tx, 'env>
+{
+    fn drop(&mut self)
+    {
+        for (account, exclusive) in self.held.drain(..).rev() {
+            unsafe {
+                if exclusive {
+                    tracking::release_exclusive(account.allocation_id());
+                    account.unlock_exclusive()
+                } else {
+                    account.unlock_shared()
+                }
+            }
+        }
+        drop_queue::purge();
+    }
+}
+
+/// Non-blocking locking common to [`Strong`] and [`Weak`], as used by
+/// [`lock_all!`].
+pub trait TryLock
+{
+    type Target: ?Sized;
+
+    fn try_lock_shared(&self) -> Option<Reading<'_, Self::Target>>;
+
+    fn try_lock_exclusive(&self) -> Option<Writing<'_, Self::Target>>;
+}
+
+impl<T: ?Sized> TryLock for Strong<T>
+{
+    type Target = T;
+
+    fn try_lock_shared(&self) -> Option<Reading<'_, T>> { self.try_read() }
+
+    fn try_lock_exclusive(&self) -> Option<Writing<'_, T>> { self.try_write() }
+}
+
+impl<T: ?Sized> TryLock for Weak<T>
+{
+    type Target = T;
+
+    fn try_lock_shared(&self) -> Option<Reading<'_, T>> { self.try_read() }
+
+    fn try_lock_exclusive(&self) -> Option<Writing<'_, T>> { self.try_write() }
+}
+
+impl<L: TryLock + ?Sized> TryLock for &L
+{
+    type Target = L::Target;
+
+    fn try_lock_shared(&self) -> Option<Reading<'_, L::Target>> { (**self).try_lock_shared() }
+
+    fn try_lock_exclusive(&self) -> Option<Writing<'_, L::Target>> { (**self).try_lock_exclusive() }
+}
+
+/// What [`Strong`], [`Weak`] and [`GenRef`] have in common, for code generic
+/// over all three. Sealed, as the crate relies on knowing every implementor.
+pub trait GenRefLike<T: ?Sized>: sealed::Sealed
+{
+    fn try_read(&self) -> Option<Reading<'_, T>>;
+
+    fn try_write(&self) -> Option<Writing<'_, T>>;
+
+    fn alias(&self) -> Weak<T>;
+
+    fn generation(&self) -> u64;
+}
+
+mod sealed
+{
+    pub trait Sealed {}
+
+    impl<T: ?Sized> Sealed for super::Strong<T> {}
+
+    impl<T: ?Sized> Sealed for super::Weak<T> {}
+
+    impl<T: ?Sized> Sealed for super::GenRef<T> {}
+}
+
+impl<T: ?Sized> GenRefLike<T> for Strong<T>
+{
+    fn try_read(&self) -> Option<Reading<'_, T>> { self.try_read() }
+
+    fn try_write(&self) -> Option<Writing<'_, T>> { self.try_write() }
+
+    fn alias(&self) -> Weak<T> { self.alias() }
+
+    fn generation(&self) -> u64 { self.generation() }
+}
+
+impl<T: ?Sized> GenRefLike<T> for Weak<T>
+{
+    fn try_read(&self) -> Option<Reading<'_, T>> { self.try_read() }
+
+    fn try_write(&self) -> Option<Writing<'_, T>> { self.try_write() }
+
+    fn alias(&self) -> Weak<T> { self.clone() }
+
+    fn generation(&self) -> u64 { self.generation() }
+}
+
+impl<T: ?Sized> GenRefLike<T> for GenRef<T>
+{
+    fn try_read(&self) -> Option<Reading<'_, T>> { self.try_read() }
+
+    fn try_write(&self) -> Option<Writing<'_, T>> { self.try_write() }
+
+    fn alias(&self) -> Weak<T> { self.alias() }
+
+    fn generation(&self) -> u64 { self.0.counter() }
+}
+
+/// Locks several strong or weak references of possibly different types at
+/// once, as in `lock_all!(read x, write y)`, giving a tuple of guards.
+/// Evaluates to `None` if any lock fails, in which case nothing stays locked.
+#[macro_export]
+macro_rules! lock_all {
+    ($($mode:ident $lock:expr),+ $(,)?) => {
+        (|| ::std::option::Option::Some(($($crate::lock_all!(@$mode $lock)?,)+)))()
+    };
+    (@read $lock:expr) => {
+        $crate::TryLock::try_lock_shared(&$lock)
+    };
+    (@write $lock:expr) => {
+        $crate::TryLock::try_lock_exclusive(&$lock)
+    };
+}
+
+const LIVE_OWNER: &str = "strong reference invalidated while owned";
+const PINNED: &str = "plain mutable access to a pinned referent";
+
 #[repr(transparent)]
-pub struct Strong<T>(RawRef<T>);
+pub struct Strong<T: ?Sized>(RawRef<T>);
 
-impl<T> Strong<T>
+impl<T: ?Sized> Strong<T>
 {
     #[cfg(test)]
     fn invariant(&self)
@@ -36,6 +299,13 @@ impl<T> Strong<T>
     #[cfg(not(test))]
     fn invariant(&self) {}
 
+    pub fn new(value: T) -> Self
+    where
+        T: Sized,
+    {
+        Self::from_box(Box::new(value))
+    }
+
     pub fn from_box(it: Box<T>) -> Self
     {
         let res = Self(RawRef::from_box(it));
@@ -43,104 +313,1456 @@ impl<T> Strong<T>
         res
     }
 
-    pub fn alias_of<F, U>(&self, f: F) -> Weak<U>
-    where
-        for<'a> F: FnOnce(&'a T) -> &'a U,
-    {
-        let acc = self.0.account();
-        let ptr = self.0.pointer();
-        Weak::new(
-            self.0
-                .clone()
-                .set_weak()
-                .map(|n| NonNull::from(unsafe { f(n.as_ref()) })),
-        )
-    }
+    /// Allocates `value` pinned. The allocation never moves anyway, and guards
+    /// on a pinned referent refuse plain mutable access, handing out
+    /// [`Writing::as_pin_mut`] instead, so the referent cannot be moved out
+    /// through an alias either.
+    pub fn pin(value: T) -> PinnedStrong<T>
+    where
+        T: Sized,
+    {
+        let res = Self::new(value);
+        res.0.account().raise_flags(tracking::PINNED);
+        PinnedStrong(res)
+    }
+
+    pub fn alias_of<F, U>(&self, f: F) -> Weak<U>
+    where
+        U: ?Sized,
+        for<'a> F: FnOnce(&'a T) -> &'a U,
+    {
+        let acc = self.0.account();
+        let ptr = self.0.pointer();
+        Weak::new(
+            self.0
+                .set_weak()
+                .map(|n| NonNull::from(unsafe { f(n.as_ref()) })),
+        )
+    }
+
+    pub fn alias(&self) -> Weak<T> { self.alias_of(|x| x) }
+
+    pub fn aliaser(&self) -> Aliaser<T>
+    {
+        self.invariant();
+        Aliaser(self.0.set_weak())
+    }
+
+    /// [`Strong::alias_of`] together with a read guard that keeps the alias
+    /// valid for as long as it is held.
+    pub fn alias_pinned<F, U>(&self, f: F) -> Option<(Reading<'_, T>, Weak<U>)>
+    where
+        U: ?Sized,
+        for<'a> F: FnOnce(&'a T) -> &'a U,
+    {
+        let reading = self.try_read()?;
+        let weak = self.alias_of(f);
+        Some((reading, weak))
+    }
+
+    /// Gives up ownership without invalidating anything. The allocation lives
+    /// on, leaked, until a weak reference takes it back with
+    /// [`Weak::try_reclaim`].
+    pub fn release(self)
+    where
+        T: 'static,
+    {
+        self.invariant();
+        tracking::release(self.id(), self.0.pointer().as_ptr());
+        std::mem::forget(self);
+    }
+
+    /// Alias plus a token that keeps the referent alive, though read-only,
+    /// even past the drop of this owner. `None` if currently write-locked.
+    ///
+    /// The token holds a shared lock for as long as it lives, so writes
+    /// through the owner or any alias fail, or block, until it is dropped. If
+    /// the owner is dropped first, the referent's drop is deferred until the
+    /// last token is released, and runs there. For globalized accounts the
+    /// deferred drop goes through a process-wide queue, so releasing the last
+    /// token on any thread runs it.
+    pub fn alias_with_keepalive(&self) -> Option<(KeepAlive<T>, Weak<T>)>
+    {
+        self.invariant();
+        if !self.0.account().try_lock_shared() {
+            return None;
+        }
+        Some((KeepAlive(self.0), self.alias()))
+    }
+
+    pub fn try_take(mut self) -> Result<Box<T>, Self>
+    {
+        self.invariant();
+        if let Some(b) = unsafe { self.0.try_consume_owned() } {
+            std::mem::forget(self);
+            Ok(b)
+        } else {
+            Err(self)
+        }
+    }
+
+    pub fn generation(&self) -> u64 { self.0.counter() }
+
+    /// Whether `weak` was aliased from this allocation, possibly through a
+    /// projection, and whether or not the account was shared in between.
+    pub fn is_aliased_by<U: ?Sized>(&self, weak: &Weak<U>) -> bool
+    {
+        self.0.same_allocation(&weak.0)
+    }
+
+    /// Address of the referent, read without locking. Dereferencing it races
+    /// with writers through aliases.
+    pub fn as_ptr(&self) -> *const T { self.0.pointer().as_ptr().as_ptr() }
+
+    /// Flattens the reference without giving up ownership, which
+    /// [`Strong::from_raw`] takes back.
+    pub fn into_raw(self) -> RawParts<T>
+    {
+        self.invariant();
+        let res = RawParts(self.0);
+        std::mem::forget(self);
+        res
+    }
+
+    /// # Safety
+    ///
+    /// `parts` must come from [`Strong::into_raw`] and be used only once.
+    pub unsafe fn from_raw(parts: RawParts<T>) -> Self
+    {
+        if !parts.is_strong() {
+            panic!("raw parts of a weak reference used as a strong reference")
+        }
+        let res = Self(parts.0);
+        res.invariant();
+        res
+    }
+
+    /// Like [`Strong::try_take`], but moves the value out and frees the box.
+    pub fn try_unwrap(self) -> Result<T, Self>
+    where
+        T: Sized,
+    {
+        self.try_take().map(|b| *b)
+    }
+
+    /// Infallible [`Strong::try_take`]: blocks until readers and writers are
+    /// gone on a globalized account, and panics if the referent is borrowed on
+    /// a thread-local one.
+    pub fn into_box(self) -> Box<T>
+    {
+        self.invariant();
+        let res = unsafe { self.0.consume_blocking() };
+        std::mem::forget(self);
+        res
+    }
+
+    pub fn into_inner_deferred(self) -> InnerFuture<T>
+    {
+        self.invariant();
+        let (tx, rx) = mpsc::channel();
+        unsafe {
+            self.0.consume_or_defer(move |b| {
+                let _ = tx.send(b);
+            });
+        }
+        std::mem::forget(self);
+        InnerFuture(rx)
+    }
+
+    /// Identifier unique to this allocation for the lifetime of the process,
+    /// unlike the generation which is reused along with the account.
+    pub fn id(&self) -> u64 { self.0.account().allocation_id() }
+
+    /// Publishes the writes made so far to consumers on other threads by
+    /// bumping the account's publication count with a release store, and
+    /// returns the new count. Pairs with [`Weak::acquire_publication`].
+    pub fn publish_fence(&self) -> u64 { self.0.account().publish() }
+
+    /// Key that exclusive locks must be acquired in ascending order of to
+    /// satisfy [`config::lock_order_check`]. Currently the allocation id.
+    pub fn lock_order_key(&self) -> u64 { self.id() }
+
+    /// Registers a hook run when this allocation is invalidated, right after
+    /// the generation bump and before the referent is dropped, including when
+    /// the drop is deferred until outstanding guards are released. The hook
+    /// runs on whichever thread that happens on.
+    pub fn on_drop<F>(&mut self, hook: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.invariant();
+        if tracking::subscribe(self.0.account(), self.0.counter(), Box::new(hook)).is_err() {
+            panic!("{}", LIVE_OWNER)
+        }
+    }
+
+    /// Clones the referent under a shared lock into a fresh allocation with its
+    /// own generation counter. `None` if the referent is write-locked.
+    pub fn clone_inner(&self) -> Option<Strong<T>>
+    where
+        T: Clone,
+    {
+        self.with_read(T::clone).map(Strong::new)
+    }
+
+    /// Write-locks the referent, or if readers are holding it, moves this owner
+    /// onto a clone of it first. Aliases of the old allocation go stale once
+    /// its readers are done. `None` if the referent is write-locked.
+    pub fn make_mut(&mut self) -> Option<Writing<'_, T>>
+    where
+        T: Clone,
+    {
+        self.invariant();
+        if let Some(writing) = Writing::try_new(self.0) {
+            return Some(writing);
+        }
+        *self = self.clone_inner()?;
+        self.try_write()
+    }
+
+    /// Moves the account over to the global ledger so the reference can cross
+    /// threads. Aliases on this thread stay valid and now lock against the
+    /// receiving thread. Panics if the referent is borrowed.
+    ///
+    /// The referent may end up dropped by whichever thread releases the last
+    /// guard on it, hence the `Send` bound.
+    ///
+    /// ```compile_fail
+    /// genref::Strong::new(std::rc::Rc::new(5)).send();
+    /// ```
+    pub fn send(self) -> Sendable<T>
+    where
+        T: Send + 'static,
+    {
+        Sendable(self.globalize())
+    }
+
+    // moves the account to the global ledger and marks the referent as safe
+    // to drop on any thread
+    fn globalize(self) -> Self
+    where
+        T: Send + 'static,
+    {
+        self.invariant();
+        let res = Strong(self.0.globalize());
+        std::mem::forget(self);
+        res.0.account().raise_flags(tracking::SENDABLE);
+        res
+    }
+
+    /// Overwrites `dest`'s referent with a clone of this one, keeping `dest`'s
+    /// aliases valid. Returns `false` if either lock could not be taken, and
+    /// `true` without doing anything if both are the same allocation.
+    pub fn clone_value_into(&self, dest: &Strong<T>) -> bool
+    where
+        T: Clone,
+    {
+        if self.0.account().allocation_id() == dest.0.account().allocation_id() {
+            return true;
+        }
+        let (Some(src), Some(mut dest)) = (self.try_read(), dest.try_write()) else {
+            return false;
+        };
+        dest.clone_from(&src);
+        true
+    }
+
+    /// Plain mutable access, for owners that have never handed out an alias of
+    /// this allocation and are not frozen.
+    pub fn get_mut(&mut self) -> Option<&mut T>
+    {
+        self.invariant();
+        let account = self.0.account();
+        if account.flags() & tracking::ALIASED != 0 || !account.try_lock_exclusive() {
+            return None;
+        }
+        unsafe { account.unlock_exclusive() };
+        Some(unsafe { self.0.pointer().as_ptr().as_mut() })
+    }
+
+    /// Computes an owned result under a shared lock that is released before
+    /// returning.
+    pub fn read_map<R, F>(&self, f: F) -> Option<R>
+    where
+        F: FnOnce(&T) -> R,
+    {
+        Some(f(&*self.try_read()?))
+    }
+
+    /// Alias of [`Strong::read_map`], named to pair with
+    /// [`Strong::with_write`].
+    pub fn with_read<R, F>(&self, f: F) -> Option<R>
+    where
+        F: FnOnce(&T) -> R,
+    {
+        self.read_map(f)
+    }
+
+    /// Runs `f` under an exclusive lock that is released before returning.
+    /// `None` if the referent is locked.
+    pub fn with_write<R, F>(&self, f: F) -> Option<R>
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        Some(f(&mut *self.try_write()?))
+    }
+
+    /// Write-locks the referent as a `Cell`, whose shared references can be
+    /// handed around freely for get/set while the lock is held. Only for
+    /// accounts that never left this thread.
+    pub fn as_cell(&self) -> Option<Writing<'_, Cell<T>>>
+    where
+        T: Copy,
+    {
+        self.invariant();
+        match self.0.account() {
+            AccountEnum::Local(l) if !l.is_sharable() => {
+                Writing::try_new(self.0.map(NonNull::cast))
+            }
+            _ => None,
+        }
+    }
+
+    /// Replaces the referent with `new` if it equals `current`, keeping
+    /// aliases valid. Gives `new` back if the values differ or the referent is
+    /// locked.
+    pub fn compare_and_swap(&self, current: &T, new: T) -> Result<(), T>
+    where
+        T: Sized + PartialEq,
+    {
+        match self.try_write() {
+            Some(mut writing) if *writing == *current => {
+                *writing = new;
+                Ok(())
+            }
+            _ => Err(new),
+        }
+    }
+
+    pub fn begin_write(&mut self) -> Option<TransactionGuard<'_, T>>
+    where
+        T: Clone,
+    {
+        let writing = self.try_write()?;
+        let snapshot = T::clone(&writing);
+        Some(TransactionGuard { writing, snapshot })
+    }
+
+    pub fn wrap<W>(self) -> Strong<W>
+    where
+        T: Sized,
+        W: TransparentWrapper<T>,
+    {
+        self.invariant();
+        let res = Strong(self.0.map(NonNull::cast));
+        std::mem::forget(self);
+        res.invariant();
+        res
+    }
+
+    /// One-way transition to a read-only strong reference that can be shared
+    /// with other threads. Aliases stay valid but can no longer be
+    /// write-locked. Panics if the referent is borrowed on this thread's
+    /// ledger or write-locked.
+    ///
+    /// The referent may end up dropped by whichever thread releases the last
+    /// reader, hence the `Send` bound.
+    pub fn freeze(self) -> FrozenStrong<T>
+    where
+        T: Send + 'static,
+    {
+        // globalizing takes the exclusive lock, so it must come first
+        let res = self.globalize();
+        if !res.0.try_freeze() {
+            panic!("cannot freeze a write-locked strong reference")
+        }
+        let frozen = FrozenStrong(res.0);
+        std::mem::forget(res);
+        frozen
+    }
+
+    pub fn try_split<F, A, B>(self, f: F) -> Result<(Strong<A>, Strong<B>), Self>
+    where
+        T: Sized,
+        F: FnOnce(T) -> (A, B),
+    {
+        let (a, b) = f(*self.try_take()?);
+        Ok((Strong::new(a), Strong::new(b)))
+    }
+
+    /// Same as a shared lock through an alias, minus the generation check,
+    /// which a live owner can never fail.
+    pub fn read_fast(&self) -> Option<Reading<'_, T>>
+    {
+        self.invariant();
+        unsafe { Reading::try_new_unchecked(self.0) }
+    }
+
+    /// Blocks until the lock is available on globalized accounts. Panics
+    /// instead on a contended thread-local account, which could never be
+    /// released while this thread waits.
+    pub fn read(&self) -> Reading<'_, T>
+    {
+        self.invariant();
+        Reading::new_blocking(self.0).expect(LIVE_OWNER)
+    }
+
+    /// Blocking counterpart of [`Strong::read`] for exclusive access.
+    pub fn write(&self) -> Writing<'_, T>
+    {
+        self.invariant();
+        Writing::new_blocking(self.0).expect(LIVE_OWNER)
+    }
+
+    pub fn try_read_until(&self, deadline: Instant) -> Option<Reading<'_, T>>
+    {
+        self.invariant();
+        Reading::try_new_until(self.0, deadline)
+    }
+
+    pub fn try_write_until(&self, deadline: Instant) -> Option<Writing<'_, T>>
+    {
+        self.invariant();
+        Writing::try_new_until(self.0, deadline)
+    }
+
+    /// Like [`Strong::try_read_until`], with a timeout too large to represent
+    /// as a deadline meaning no timeout at all. Thread-local accounts are
+    /// only ever tried once, as no other thread could release them.
+    pub fn try_read_for(&self, timeout: Duration) -> Option<Reading<'_, T>>
+    {
+        match Instant::now().checked_add(timeout) {
+            Some(deadline) => self.try_read_until(deadline),
+            None if self.0.account().is_thread_local() => self.try_read(),
+            None => Some(self.read()),
+        }
+    }
+
+    pub fn try_write_for(&self, timeout: Duration) -> Option<Writing<'_, T>>
+    {
+        match Instant::now().checked_add(timeout) {
+            Some(deadline) => self.try_write_until(deadline),
+            None if self.0.account().is_thread_local() => self.try_write(),
+            None => Some(self.write()),
+        }
+    }
+
+    fn try_read(&self) -> Option<Reading<'_, T>>
+    {
+        self.invariant();
+        Reading::try_new(self.0)
+    }
+
+    fn try_write(&self) -> Option<Writing<'_, T>>
+    {
+        self.invariant();
+        Writing::try_new(self.0)
+    }
+}
+
+impl<T: ?Sized + Unsize<U>, U: ?Sized> CoerceUnsized<Strong<U>> for Strong<T> {}
+
+impl<T: ?Sized> Drop for Strong<T>
+{
+    fn drop(&mut self)
+    {
+        self.invariant();
+        unsafe { self.0.consume_or_defer(drop) }
+    }
+}
+
+fn debug_reference<T: std::fmt::Debug + ?Sized>(
+    name: &str, raw_ref: &RawRef<T>, value: Option<&T>, f: &mut std::fmt::Formatter<'_>,
+) -> std::fmt::Result
+{
+    let backend = match raw_ref.account() {
+        AccountEnum::Local(l) if !l.is_sharable() => "local",
+        _ => "global",
+    };
+    let mut res = f.debug_struct(name);
+    res.field("backend", &backend)
+        .field("generation", &raw_ref.counter());
+    match value {
+        Some(value) => res.field("value", &value),
+        None => match Reading::try_new(*raw_ref) {
+            Some(reading) => res.field("value", &&*reading),
+            None if raw_ref.is_valid() => res.field("value", &format_args!("<locked>")),
+            None => res.field("value", &format_args!("<invalid>")),
+        },
+    };
+    res.finish()
+}
+
+impl<T: std::fmt::Debug + ?Sized> std::fmt::Debug for Strong<T>
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        debug_reference("Strong", &self.0, None, f)
+    }
+}
+
+impl<T: ?Sized> std::fmt::Pointer for Strong<T>
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        std::fmt::Pointer::fmt(&self.0.pointer().as_ptr(), f)
+    }
+}
+
+/// Flattened [`Strong`] or [`Weak`], keeping the account, pointer, generation
+/// and ownership flag so that the round trip is lossless.
+#[repr(transparent)]
+pub struct RawParts<T: ?Sized>(RawRef<T>);
+
+impl<T: ?Sized> Clone for RawParts<T>
+{
+    fn clone(&self) -> Self { *self }
+}
+
+impl<T: ?Sized> Copy for RawParts<T> {}
+
+impl<T: ?Sized> RawParts<T>
+{
+    pub fn as_ptr(&self) -> *const T { self.0.pointer().as_ptr().as_ptr() }
+
+    pub fn is_strong(&self) -> bool { matches!(self.0.pointer(), PointerEnum::Strong(_)) }
+}
+
+/// Cheap factory for aliases of one allocation, capturing its generation once.
+///
+/// Weaks minted after the allocation is invalidated carry the old generation
+/// and are simply invalid, never dangling.
+pub struct Aliaser<T: ?Sized>(RawRef<T>);
+
+impl<T: ?Sized> Clone for Aliaser<T>
+{
+    fn clone(&self) -> Self { *self }
+}
+
+impl<T: ?Sized> Copy for Aliaser<T> {}
+
+impl<T: ?Sized> Aliaser<T>
+{
+    pub fn mint(&self) -> Weak<T> { Weak::new(self.0) }
+}
+
+/// Ownership of a referent that was still borrowed when it was given up.
+pub struct InnerFuture<T: ?Sized>(mpsc::Receiver<Box<T>>);
+
+impl<T: ?Sized> InnerFuture<T>
+{
+    pub fn try_get(&self) -> Option<Box<T>> { self.0.try_recv().ok() }
+}
+
+impl<T> Strong<Vec<T>>
+{
+    pub fn read_while<F>(&self, mut pred: F) -> Option<usize>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        let vec = self.try_read()?;
+        Some(vec.iter().take_while(|x| pred(x)).count())
+    }
+
+    /// Runs a batch of edits under a single exclusive lock. Returns `false`
+    /// without running them if the lock is unavailable.
+    pub fn apply<F>(&self, edits: F) -> bool
+    where
+        F: FnOnce(&mut Vec<T>),
+    {
+        match self.try_write() {
+            Some(mut vec) => {
+                edits(&mut vec);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Alias of a single element, sharing the vector's account.
+    ///
+    /// # Safety
+    ///
+    /// The generation only changes when the vector itself is invalidated, so
+    /// an element alias is **not** invalidated when a `push` or other
+    /// reallocation moves the buffer. The vector must not be resized while the
+    /// returned alias is in use. [`Strong::alias_element`] on a
+    /// `Strong<[T]>` is the safe alternative for buffers that never grow.
+    pub unsafe fn alias_index(&self, index: usize) -> Option<Weak<T>>
+    {
+        let vec = self.try_read()?;
+        let elem = NonNull::from(vec.get(index)?);
+        Some(Weak::new(self.0.set_weak().map(|_| elem)))
+    }
+
+    pub fn fold<B, F>(&self, init: B, f: F) -> Option<B>
+    where
+        F: FnMut(B, &T) -> B,
+    {
+        let vec = self.try_read()?;
+        Some(vec.iter().fold(init, f))
+    }
+
+    pub fn snapshot(&self) -> Option<Vec<T>>
+    where
+        T: Clone,
+    {
+        let vec = self.try_read()?;
+        Some(vec.to_vec())
+    }
+
+    /// Write-locks the vector once for a set of distinct, in-bounds indices.
+    /// Returns `None` on duplicates, out-of-bounds indices or lock failure.
+    pub fn get_disjoint_mut(&self, indices: &[usize]) -> Option<DisjointMut<'_, T>>
+    {
+        let writing = self.try_write()?;
+        for (i, &ix) in indices.iter().enumerate() {
+            if ix >= writing.len() || indices[..i].contains(&ix) {
+                return None;
+            }
+        }
+        Some(DisjointMut {
+            writing,
+            indices: indices.to_vec(),
+        })
+    }
+}
+
+/// Elements of a write-locked vector at distinct indices, addressed by their
+/// position in the index set. See [`Strong::get_disjoint_mut`].
+pub struct DisjointMut<'a, T>
+{
+    writing: Writing<'a, Vec<T>>,
+    indices: Vec<usize>,
+}
+
+impl<'a, T> DisjointMut<'a, T>
+{
+    pub fn len(&self) -> usize { self.indices.len() }
+
+    pub fn is_empty(&self) -> bool { self.indices.is_empty() }
+
+    /// All elements mutably at once, in index-set order.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T>
+    {
+        let base = self.writing.as_mut_ptr();
+        // indices were checked distinct and in bounds
+        self.indices
+            .iter()
+            .map(move |&ix| unsafe { &mut *base.add(ix) })
+    }
+}
+
+impl<'a, T> std::ops::Index<usize> for DisjointMut<'a, T>
+{
+    type Output = T;
+
+    fn index(&self, i: usize) -> &T { &self.writing[self.indices[i]] }
+}
+
+impl<'a, T> std::ops::IndexMut<usize> for DisjointMut<'a, T>
+{
+    fn index_mut(&mut self, i: usize) -> &mut T { &mut self.writing[self.indices[i]] }
+}
+
+impl<T> Strong<[T]>
+{
+    /// Shrinks the vector's buffer to fit, then shares it as a single
+    /// allocation under one generation counter.
+    pub fn from_vec(it: Vec<T>) -> Self { Self::from_boxed_slice(it.into_boxed_slice()) }
+
+    pub fn from_boxed_slice(it: Box<[T]>) -> Self { Self::from_box(it) }
+
+    /// Alias of a single element, sharing the slice's generation counter.
+    /// Unlike [`Strong::alias_index`] this is safe, as a slice allocation can
+    /// never be resized. Panics if `index` is out of bounds.
+    pub fn alias_element(&self, index: usize) -> Weak<T>
+    {
+        self.invariant();
+        Weak::new(self.0.set_weak().map(|slice| {
+            assert!(index < slice.len(), "element alias out of bounds");
+            unsafe { NonNull::new_unchecked(slice.as_ptr().cast::<T>().add(index)) }
+        }))
+    }
+
+    /// Alias of a subslice, sharing the slice's generation counter. Panics if
+    /// `range` is out of bounds.
+    pub fn alias_range(&self, range: Range<usize>) -> Weak<[T]>
+    {
+        self.invariant();
+        Weak::new(self.0.set_weak().map(|slice| {
+            assert!(
+                range.start <= range.end && range.end <= slice.len(),
+                "range alias out of bounds"
+            );
+            let start =
+                unsafe { NonNull::new_unchecked(slice.as_ptr().cast::<T>().add(range.start)) };
+            NonNull::slice_from_raw_parts(start, range.end - range.start)
+        }))
+    }
+}
+
+impl<A, B> Strong<(A, B)>
+{
+    /// Gives each half of the tuple its own allocation and counter,
+    /// invalidating aliases of the pair. Hands the pair back if it is locked.
+    pub fn split_owned(self) -> Result<(Strong<A>, Strong<B>), Self> { self.try_split(|pair| pair) }
+}
+
+impl<T: Send + 'static> Strong<Vec<T>>
+{
+    /// Freezes the buffer and leaks it, handing out a read-only view that can
+    /// be copied freely and read from any thread without locking. Existing
+    /// aliases stay valid for reading. Panics like [`Strong::freeze`].
+    pub fn publish(self) -> SharedReadOnly<T>
+    {
+        let frozen = self.freeze();
+        let slice = unsafe { &*(frozen.get().as_slice() as *const [T]) };
+        std::mem::forget(frozen);
+        SharedReadOnly(slice)
+    }
+}
+
+/// Strong reference to a pinned referent, see [`Strong::pin`]. Only lends out
+/// the strong reference, since nothing reachable through a shared reference to
+/// it can move the referent. Dropping it drops the referent in place.
+pub struct PinnedStrong<T: ?Sized>(Strong<T>);
+
+impl<T: ?Sized> Deref for PinnedStrong<T>
+{
+    type Target = Strong<T>;
+
+    fn deref(&self) -> &Strong<T> { &self.0 }
+}
+
+/// Shared lock held without borrowing the owner, blocking writers while it
+/// lives. See [`Strong::alias_with_keepalive`]. Dropping the owner while
+/// tokens remain defers the drop until the last one is released.
+pub struct KeepAlive<T: ?Sized>(RawRef<T>);
+
+impl<T: ?Sized> Drop for KeepAlive<T>
+{
+    fn drop(&mut self)
+    {
+        unsafe { self.0.account().unlock_shared() };
+        drop_queue::purge();
+    }
+}
+
+/// Published, permanently frozen buffer. See [`Strong::publish`].
+pub struct SharedReadOnly<T: 'static>(&'static [T]);
+
+impl<T> Clone for SharedReadOnly<T>
+{
+    fn clone(&self) -> Self { *self }
+}
+
+impl<T> Copy for SharedReadOnly<T> {}
+
+impl<T> Deref for SharedReadOnly<T>
+{
+    type Target = [T];
+
+    fn deref(&self) -> &Self::Target { self.0 }
+}
+
+impl Strong<Vec<u8>>
+{
+    pub fn checksum(&self) -> Option<u64>
+    {
+        use std::hash::Hasher;
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        hasher.write(&self.try_read()?);
+        Some(hasher.finish())
+    }
+
+    pub fn verify_checksum(&self, expected: u64) -> Option<bool>
+    {
+        Some(self.checksum()? == expected)
+    }
+}
+
+#[cfg(feature = "serde")]
+#[derive(Debug)]
+pub enum JsonError
+{
+    Json(serde_json::Error),
+    Locked,
+}
+
+#[cfg(feature = "serde")]
+impl<T> Strong<T>
+{
+    pub fn to_json(&self) -> Option<String>
+    where
+        T: ::serde::Serialize,
+    {
+        serde_json::to_string(&*self.try_read()?).ok()
+    }
+
+    /// Parses before locking, so a malformed payload never blocks readers.
+    pub fn update_from_json(&self, s: &str) -> Result<(), JsonError>
+    where
+        T: ::serde::de::DeserializeOwned,
+    {
+        let value = serde_json::from_str(s).map_err(JsonError::Json)?;
+        *self.try_write().ok_or(JsonError::Locked)? = value;
+        Ok(())
+    }
+}
+
+/// Strong reference to a referent that is never written again. It holds a
+/// shared lock on its account for its entire lifetime, so it has no write
+/// methods, and [`FrozenStrong::get`] reads without touching the lock. Its
+/// account is always global, so it can be sent and shared across threads.
+///
+/// Writing is a compile error:
+///
+/// ```compile_fail
+/// let frozen = genref::FrozenStrong::new(5);
+/// frozen.try_write();
+/// ```
+///
+/// ```compile_fail
+/// let frozen = genref::FrozenStrong::new(5);
+/// frozen.alias().write();
+/// ```
+///
+/// So is freezing a referent that cannot be dropped on another thread:
+///
+/// ```compile_fail
+/// genref::FrozenStrong::new(std::rc::Rc::new(5));
+/// ```
+pub struct FrozenStrong<T: ?Sized>(RawRef<T>);
+
+unsafe impl<T: Send + Sync + ?Sized> Send for FrozenStrong<T> {}
+
+unsafe impl<T: Send + Sync + ?Sized> Sync for FrozenStrong<T> {}
+
+impl<T: ?Sized> FrozenStrong<T>
+{
+    pub fn new(value: T) -> Self
+    where
+        T: Sized + Send + 'static,
+    {
+        Self::from_box(Box::new(value))
+    }
+
+    pub fn from_box(it: Box<T>) -> Self
+    where
+        T: Send + 'static,
+    {
+        Strong::from_box(it).freeze()
+    }
+
+    pub fn get(&self) -> &T
+    {
+        self.0.invariant();
+        unsafe { self.0.pointer().as_ptr().as_ref() }
+    }
+
+    pub fn alias(&self) -> FrozenWeak<T> { FrozenWeak(self.0.set_weak()) }
+
+    pub fn generation(&self) -> u64 { self.0.counter() }
+
+    /// See [`Strong::id`].
+    pub fn id(&self) -> u64 { self.0.account().allocation_id() }
+}
+
+impl<T: ?Sized> Drop for FrozenStrong<T>
+{
+    fn drop(&mut self)
+    {
+        self.0.invariant();
+        unsafe { self.0.consume_or_defer(drop) }
+    }
+}
+
+impl<T: std::fmt::Debug + ?Sized> std::fmt::Debug for FrozenStrong<T>
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        debug_reference("FrozenStrong", &self.0, Some(self.get()), f)
+    }
+}
+
+/// Read-only alias of a [`FrozenStrong`].
+pub struct FrozenWeak<T: ?Sized>(RawRef<T>);
+
+unsafe impl<T: Send + Sync + ?Sized> Send for FrozenWeak<T> {}
+
+unsafe impl<T: Send + Sync + ?Sized> Sync for FrozenWeak<T> {}
+
+impl<T: ?Sized> Clone for FrozenWeak<T>
+{
+    fn clone(&self) -> Self { *self }
+}
+
+impl<T: ?Sized> Copy for FrozenWeak<T> {}
+
+impl<T: ?Sized> FrozenWeak<T>
+{
+    /// `None` once the owner is gone. Readers never contend with a writer, so
+    /// this cannot fail otherwise.
+    pub fn try_read(&self) -> Option<Reading<'_, T>> { Reading::try_new(self.0) }
+
+    pub fn is_valid(&self) -> bool { self.0.is_valid() }
+
+    pub fn generation(&self) -> u64 { self.0.counter() }
+}
+
+impl<T: std::fmt::Debug + ?Sized> std::fmt::Debug for FrozenWeak<T>
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        debug_reference("FrozenWeak", &self.0, None, f)
+    }
+}
+
+#[repr(transparent)]
+pub struct Weak<T: ?Sized>(RawRef<T>);
+impl<T: ?Sized> Clone for Weak<T>
+{
+    fn clone(&self) -> Self { Self(self.0) }
+}
+
+impl<T: ?Sized + Unsize<U>, U: ?Sized> CoerceUnsized<Weak<U>> for Weak<T> {}
+
+/// Identity, not value, equality. See [`Weak::ptr_eq`].
+impl<T: ?Sized> PartialEq for Weak<T>
+{
+    fn eq(&self, other: &Self) -> bool { self.ptr_eq(other) }
+}
+
+impl<T: ?Sized> Eq for Weak<T> {}
+
+impl<T: ?Sized> std::hash::Hash for Weak<T>
+{
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H)
+    {
+        self.0.account().allocation_id().hash(state);
+        self.0.counter().hash(state);
+    }
+}
+
+impl<T> Default for Weak<T>
+{
+    fn default() -> Self { Self::dangling() }
+}
+
+impl<T: std::fmt::Debug + ?Sized> std::fmt::Debug for Weak<T>
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        debug_reference("Weak", &self.0, None, f)
+    }
+}
+
+impl<T: ?Sized> std::fmt::Pointer for Weak<T>
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        std::fmt::Pointer::fmt(&self.0.pointer().as_ptr(), f)
+    }
+}
+
+impl<T: ?Sized> Weak<T>
+{
+    fn invariant(&self)
+    {
+        self.0.invariant();
+        assert_matches!(
+            self.0.pointer(),
+            PointerEnum::Weak(_),
+            "weak reference without weak flag"
+        )
+    }
+
+    fn new(raw_ref: RawRef<T>) -> Self
+    {
+        let res = Weak(raw_ref);
+        res.invariant();
+        res
+    }
+
+    pub fn try_read(&self) -> Option<Reading<'_, T>> { Reading::try_new(self.0) }
+
+    pub fn try_write(&self) -> Option<Writing<'_, T>> { Writing::try_new(self.0) }
+
+    /// Weak reference that is never valid, for use as a sentinel.
+    pub const fn dangling() -> Self
+    where
+        T: Sized,
+    {
+        Weak(RawRef::dangling())
+    }
+
+    /// Lock-free check that the referent has not been invalidated. A `true`
+    /// can go stale right away unless a lock is taken.
+    pub fn is_valid(&self) -> bool { self.0.is_valid() }
+
+    /// The generation this reference was aliased at.
+    pub fn generation(&self) -> u64 { self.0.counter() }
+
+    /// Whether both refer to the same allocation generation. Projections of
+    /// one allocation compare equal, as do aliases taken before and after it
+    /// was shared. Once the referent is dropped and its account reused, the
+    /// identity of a stale reference changes with it, so prune stale keys
+    /// from sets and maps with [`Weak::is_valid`].
+    pub fn ptr_eq(&self, other: &Weak<T>) -> bool { self.0.same_allocation(&other.0) }
+
+    /// Address of the referent, read without locking or validating.
+    /// Dereferencing it is only sound under a lock and while valid.
+    pub fn as_ptr(&self) -> *const T { self.0.pointer().as_ptr().as_ptr() }
+
+    pub fn into_raw(self) -> RawParts<T> { RawParts(self.0) }
+
+    /// # Safety
+    ///
+    /// `parts` must come from [`Weak::into_raw`]. Unlike strong references,
+    /// weak raw parts may be reused any number of times.
+    pub unsafe fn from_raw(parts: RawParts<T>) -> Self
+    {
+        if parts.is_strong() {
+            panic!("raw parts of a strong reference used as a weak reference")
+        }
+        Self::new(parts.0)
+    }
+
+    /// Recovers ownership parked by [`Strong::release`]. Only succeeds through
+    /// an unprojected alias, and while nothing holds the lock.
+    pub fn try_reclaim(&self) -> Option<Strong<T>>
+    where
+        T: 'static,
+    {
+        let account = self.0.account();
+        if !account.try_lock_exclusive() {
+            return None;
+        }
+        let reclaimed = self.0.is_valid()
+            && tracking::reclaim(account.allocation_id(), self.0.pointer().as_ptr());
+        unsafe { account.unlock_exclusive() };
+        reclaimed.then(|| Strong(self.0.set_strong()))
+    }
+
+    /// Blocks like [`Strong::read`], but returns `None` if the referent was
+    /// invalidated.
+    pub fn read(&self) -> Option<Reading<'_, T>> { Reading::new_blocking(self.0) }
+
+    /// Also `None` if the referent was frozen, as it can never be written
+    /// again.
+    pub fn write(&self) -> Option<Writing<'_, T>> { Writing::new_blocking(self.0) }
+
+    /// Repoints this weak reference at `onto`, for when the referent was
+    /// moved there and is logically the same object. Nothing checks that it
+    /// is.
+    pub fn rebase(&mut self, onto: &Strong<T>) { *self = onto.alias() }
+
+    /// Loads the account's publication count with acquire ordering, then
+    /// checks validity. Writes made before the [`Strong::publish_fence`]
+    /// that returned the count, or a lower one, are visible once it is
+    /// returned. `None` if the referent was invalidated.
+    pub fn acquire_publication(&self) -> Option<u64>
+    {
+        let published = self.0.account().published();
+        self.0.is_valid().then_some(published)
+    }
+
+    /// [`Weak::acquire_publication`] without the count, so only the
+    /// publications it happened to observe are visible.
+    pub fn acquire_validity(&self) -> bool { self.acquire_publication().is_some() }
+
+    /// Moves the account over to the global ledger like [`Strong::send`], so
+    /// the alias can be used from any thread. Panics if the referent is
+    /// borrowed on this thread's ledger.
+    ///
+    /// The alias may be a projection, so nothing is known about the owner's
+    /// referent, and its drop stays on the owner's thread: if the owner is
+    /// dropped while another thread holds the lock, the referent is only
+    /// dropped by a later guard release or [`sweep`] on the owner's thread.
+    pub fn share(self) -> Shareable<T>
+    where
+        T: 'static,
+    {
+        Shareable(Weak::new(self.0.globalize()))
+    }
+
+    /// Projects under a briefly held read lock, sharing this reference's
+    /// counter. `None` if the referent is invalid or write-locked.
+    pub fn alias_of<F, U>(&self, f: F) -> Option<Weak<U>>
+    where
+        U: ?Sized,
+        for<'a> F: FnOnce(&'a T) -> &'a U,
+    {
+        let reading = self.try_read()?;
+        let target = NonNull::from(f(&reading));
+        Some(Weak::new(self.0.map(|_| target)))
+    }
+
+    /// Projects under a read lock. `Ok(None)` means the lock could not be
+    /// taken; errors from the projection are passed through.
+    pub fn try_map_result<U, E, F>(&self, f: F) -> Result<Option<Weak<U>>, E>
+    where
+        U: ?Sized,
+        for<'a> F: FnOnce(&'a T) -> Result<&'a U, E>,
+    {
+        let Some(reading) = self.try_read() else {
+            return Ok(None);
+        };
+        let target = NonNull::from(f(&reading)?);
+        Ok(Some(Weak::new(self.0.map(|_| target))))
+    }
+
+    /// Waits for the lock until `deadline` on globalized accounts. Thread-local
+    /// accounts cannot be released by anyone else while this thread waits, so
+    /// they are tried once.
+    pub fn try_read_until(&self, deadline: Instant) -> Option<Reading<'_, T>>
+    {
+        Reading::try_new_until(self.0, deadline)
+    }
+
+    pub fn try_write_until(&self, deadline: Instant) -> Option<Writing<'_, T>>
+    {
+        Writing::try_new_until(self.0, deadline)
+    }
+
+    pub fn try_read_for(&self, timeout: Duration) -> Option<Reading<'_, T>>
+    {
+        match Instant::now().checked_add(timeout) {
+            Some(deadline) => self.try_read_until(deadline),
+            None if self.0.account().is_thread_local() => self.try_read(),
+            None => self.read(),
+        }
+    }
+
+    pub fn try_write_for(&self, timeout: Duration) -> Option<Writing<'_, T>>
+    {
+        match Instant::now().checked_add(timeout) {
+            Some(deadline) => self.try_write_until(deadline),
+            None if self.0.account().is_thread_local() => self.try_write(),
+            None => self.write(),
+        }
+    }
+
+    /// The [`Strong::id`] of the referent, or `None` if it has been
+    /// invalidated.
+    pub fn source_id(&self) -> Option<u64>
+    {
+        let id = self.0.account().allocation_id();
+        self.0.is_valid().then_some(id)
+    }
+
+    /// Runs `f` under a shared lock that is released before returning. `None`
+    /// if the referent is invalid or write-locked.
+    pub fn with_read<R, F>(&self, f: F) -> Option<R>
+    where
+        F: FnOnce(&T) -> R,
+    {
+        Some(f(&*self.try_read()?))
+    }
+
+    /// Runs `f` under an exclusive lock that is released before returning.
+    /// `None` if the referent is invalid or locked.
+    pub fn with_write<R, F>(&self, f: F) -> Option<R>
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        Some(f(&mut *self.try_write()?))
+    }
+
+    /// Runs `read` under a shared lock and, if it asks to escalate, upgrades
+    /// to an exclusive lock and runs `write`.
+    ///
+    /// Returns `None` if the referent is invalid, locked, or if escalation
+    /// fails because other readers are present.
+    pub fn with<R, F, G>(&self, read: F, write: G) -> Option<R>
+    where
+        F: FnOnce(&T) -> ReadResult<R>,
+        G: FnOnce(&mut T) -> R,
+    {
+        let reading = self.try_read()?;
+        match read(&reading) {
+            ReadResult::Done(res) => Some(res),
+            ReadResult::Escalate => {
+                let mut writing = reading.try_upgrade().ok()?;
+                Some(write(&mut writing))
+            }
+        }
+    }
+
+    /// Registers a one-shot callback run on whichever thread invalidates the
+    /// referent, or immediately if it already has been.
+    pub fn on_invalidate<F>(&self, callback: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        if let Err(callback) =
+            tracking::subscribe(self.0.account(), self.0.counter(), Box::new(callback))
+        {
+            callback()
+        }
+    }
+}
+
+/// Weak reference that rebuilds its referent once the original owner drops
+/// it, keeping the rebuilt strong reference for itself.
+pub struct SelfHealing<T>
+{
+    weak: Weak<T>,
+    owned: Option<Strong<T>>,
+}
+
+impl<T> SelfHealing<T>
+{
+    pub fn new(weak: Weak<T>) -> Self { Self { weak, owned: None } }
+
+    /// Only rebuilds when the weak reference is stale; a live but
+    /// write-locked referent yields `None`.
+    pub fn get_or_rebuild<F>(&mut self, rebuild: F) -> Option<Reading<'_, T>>
+    where
+        F: FnOnce() -> Strong<T>,
+    {
+        if !self.weak.0.is_valid() {
+            let strong = rebuild();
+            self.weak = strong.alias();
+            self.owned = Some(strong);
+        }
+        self.weak.try_read()
+    }
+}
+
+/// A group of weak references validated and read-locked together, whose
+/// elements can then be accessed without any further checks. Dropping the
+/// batch releases every lock.
+pub struct ValidatedBatch<'a, T>(Vec<Reading<'a, T>>);
+
+impl<'a, T> ValidatedBatch<'a, T>
+{
+    pub fn new(weaks: &'a [Weak<T>]) -> Option<Self>
+    {
+        weaks
+            .iter()
+            .map(Weak::try_read)
+            .collect::<Option<_>>()
+            .map(Self)
+    }
+
+    pub fn len(&self) -> usize { self.0.len() }
+
+    pub fn is_empty(&self) -> bool { self.0.is_empty() }
+
+    pub fn get(&self, index: usize) -> Option<&T> { self.0.get(index).map(|r| &**r) }
+}
+
+/// What [`read_all`] and [`write_all`] do about entries that are no longer
+/// valid. Locked entries always make them fail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidPolicy
+{
+    Fail,
+    Skip,
+}
+
+/// Read-locks every weak reference in `weaks`, or none of them. Skipped
+/// entries are left out of the result.
+pub fn read_all<T: ?Sized>(weaks: &[Weak<T>], policy: InvalidPolicy)
+    -> Option<Vec<Reading<'_, T>>>
+{
+    lock_all_with(weaks, policy, Weak::try_read)
+}
+
+/// Write-locks every weak reference in `weaks`, or none of them. Skipped
+/// entries are left out of the result.
+pub fn write_all<T: ?Sized>(weaks: &[Weak<T>], policy: InvalidPolicy)
+    -> Option<Vec<Writing<'_, T>>>
+{
+    lock_all_with(weaks, policy, Weak::try_write)
+}
+
+fn lock_all_with<'a, T: ?Sized, G>(
+    weaks: &'a [Weak<T>], policy: InvalidPolicy, lock: fn(&'a Weak<T>) -> Option<G>,
+) -> Option<Vec<G>>
+{
+    let mut res = Vec::with_capacity(weaks.len());
+    for weak in weaks {
+        match lock(weak) {
+            Some(guard) => res.push(guard),
+            None if policy == InvalidPolicy::Skip && !weak.is_valid() => {}
+            None => return None,
+        }
+    }
+    Some(res)
+}
+
+pub enum ReadResult<R>
+{
+    Done(R),
+    Escalate,
+}
 
-    pub fn alias(&self) -> Weak<T> { self.alias_of(|x| x) }
+/// Either a [`Strong`] or a [`Weak`] reference, distinguished at runtime by
+/// the flag in its generation word rather than by a discriminant, so that
+/// containers can hold both kinds uniformly.
+#[repr(transparent)]
+pub struct GenRef<T: ?Sized>(RawRef<T>);
 
-    pub fn try_take(mut self) -> Result<Box<T>, Self>
+impl<T: ?Sized> GenRef<T>
+{
+    pub fn is_strong(&self) -> bool { matches!(self.0.pointer(), PointerEnum::Strong(_)) }
+
+    pub fn try_read(&self) -> Option<Reading<'_, T>> { Reading::try_new(self.0) }
+
+    pub fn try_write(&self) -> Option<Writing<'_, T>> { Writing::try_new(self.0) }
+
+    pub fn alias(&self) -> Weak<T>
     {
-        self.invariant();
-        if let Some(b) = unsafe { self.0.try_consume_exclusive() } {
-            std::mem::forget(self);
-            Ok(b)
-        } else {
-            Err(self)
+        match self.0.pointer() {
+            PointerEnum::Strong(_) => Weak::new(self.0.set_weak()),
+            PointerEnum::Weak(_) => Weak::new(self.0),
         }
     }
 
-    fn try_read(&self) -> Option<Reading<T>>
+    /// Moves the account over to the global ledger like [`Strong::send`] or
+    /// [`Weak::share`], depending on the kind of reference.
+    pub fn transfer(self) -> Transferrable<T>
+    where
+        T: Send + 'static,
     {
-        self.invariant();
-        Reading::try_new(self.0.clone())
+        let res = GenRef(self.0.globalize());
+        std::mem::forget(self);
+        if res.is_strong() {
+            res.0.account().raise_flags(tracking::SENDABLE);
+        }
+        Transferrable(res)
     }
 
-    fn try_write(&self) -> Option<Writing<T>>
+    pub fn into_enum(self) -> GenRefEnum<T>
     {
-        self.invariant();
-        Writing::try_new(self.0.clone())
+        let raw_ref = self.0;
+        std::mem::forget(self);
+        match raw_ref.pointer() {
+            PointerEnum::Strong(_) => GenRefEnum::Strong(Strong(raw_ref)),
+            PointerEnum::Weak(_) => GenRefEnum::Weak(Weak(raw_ref)),
+        }
     }
 }
 
-impl<T> Drop for Strong<T>
+impl<T: ?Sized> Drop for GenRef<T>
 {
     fn drop(&mut self)
     {
-        self.invariant();
-        unsafe {
-            self.0.try_consume_exclusive();
+        if self.is_strong() {
+            drop(Strong(self.0))
         }
     }
 }
 
-#[repr(transparent)]
-pub struct Weak<T>(RawRef<T>);
-impl<T> Clone for Weak<T>
-{
-    fn clone(&self) -> Self { Self(self.0.clone()) }
-}
-
-impl<T> Weak<T>
+impl<T: std::fmt::Debug + ?Sized> std::fmt::Debug for GenRef<T>
 {
-    fn invariant(&self)
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
     {
-        self.0.invariant();
-        assert_matches!(
-            self.0.pointer(),
-            PointerEnum::Weak(_),
-            "weak reference without weak flag"
-        )
+        debug_reference("GenRef", &self.0, None, f)
     }
+}
 
-    fn new(raw_ref: RawRef<T>) -> Self
+impl<T: ?Sized> From<Strong<T>> for GenRef<T>
+{
+    fn from(strong: Strong<T>) -> Self
     {
-        let res = Weak(raw_ref);
-        res.invariant();
+        let res = GenRef(strong.0);
+        std::mem::forget(strong);
         res
     }
+}
 
-    pub fn try_read(&self) -> Option<Reading<T>> { Reading::try_new(self.0.clone()) }
+impl<T: ?Sized> From<Weak<T>> for GenRef<T>
+{
+    fn from(weak: Weak<T>) -> Self { GenRef(weak.0) }
+}
 
-    pub fn try_write(&self) -> Option<Writing<T>> { Writing::try_new(self.0.clone()) }
+impl<T: ?Sized> From<GenRefEnum<T>> for GenRef<T>
+{
+    fn from(it: GenRefEnum<T>) -> Self
+    {
+        match it {
+            GenRefEnum::Weak(weak) => weak.into(),
+            GenRefEnum::Strong(strong) => strong.into(),
+        }
+    }
 }
 
-#[repr(transparent)]
-struct GenRef<T>(RawRef<T>);
-pub enum GenRefEnum<T>
+#[derive(Debug)]
+pub enum GenRefEnum<T: ?Sized>
 {
     Weak(Weak<T>),
     Strong(Strong<T>),
 }
 
 #[repr(transparent)]
-pub struct Reading<'a, T>(RawRef<T>, PhantomData<&'a ()>);
+pub struct Reading<'a, T: ?Sized>(RawRef<T>, PhantomData<&'a ()>);
+
+/// Guards only track the account, so a mapped guard is an ordinary guard over
+/// the projected place.
+pub type MappedReading<'a, T> = Reading<'a, T>;
 
-impl<'a, T> Reading<'a, T>
+/// Guard that borrows nothing, so it can be stored or moved into callbacks.
+/// The lock alone keeps the allocation alive, since dropping the owner defers
+/// to the guard's release. See [`Reading::into_owned`].
+pub type OwnedReading<T> = Reading<'static, T>;
+
+impl<'a, T: ?Sized> Reading<'a, T>
 {
     fn invariant(&self) { self.0.invariant(); }
 
     pub(crate) fn try_new(raw_ref: RawRef<T>) -> Option<Self>
+    {
+        Self::try_new_with(raw_ref, AccountEnum::try_lock_shared)
+    }
+
+    pub(crate) fn try_new_until(raw_ref: RawRef<T>, deadline: Instant) -> Option<Self>
+    {
+        Self::try_new_with(raw_ref, |account| account.try_lock_shared_until(deadline))
+    }
+
+    pub(crate) fn new_blocking(raw_ref: RawRef<T>) -> Option<Self>
+    {
+        Self::try_new_with(raw_ref, |account| {
+            account.lock_shared();
+            true
+        })
+    }
+
+    fn try_new_with<F>(raw_ref: RawRef<T>, lock: F) -> Option<Self>
+    where
+        F: FnOnce(&AccountEnum) -> bool,
+    {
+        raw_ref.invariant();
+        let account = raw_ref.account();
+        if lock(&account) {
+            if !raw_ref.is_valid() {
+                unsafe { account.unlock_shared() };
+                return None;
+            }
+            let res = Self(raw_ref, PhantomData);
+            res.invariant();
+            Some(res)
+        } else {
+            None
+        }
+    }
+
+    /// Volatile read of the whole referent, for memory-mapped payloads. `T`
+    /// should be a `repr(C)` or primitive type whose size matches the
+    /// hardware access width.
+    pub fn read_volatile(&self) -> T
+    where
+        T: Copy,
+    {
+        unsafe { std::ptr::read_volatile(self.0.pointer().as_ptr().as_ptr()) }
+    }
+
+    /// assumes the reference is known to be valid
+    pub(crate) unsafe fn try_new_unchecked(raw_ref: RawRef<T>) -> Option<Self>
     {
         raw_ref.invariant();
         if raw_ref.account().try_lock_shared() {
@@ -151,88 +1773,470 @@ impl<'a, T> Reading<'a, T>
             None
         }
     }
+
+    /// Narrows the guard to part of the referent, keeping the same lock.
+    pub fn map<U, F>(self, f: F) -> MappedReading<'a, U>
+    where
+        U: ?Sized,
+        for<'b> F: FnOnce(&'b T) -> &'b U,
+    {
+        self.invariant();
+        let target = NonNull::from(f(&self));
+        let raw_ref = self.0;
+        std::mem::forget(self);
+        Reading(raw_ref.map(|_| target), PhantomData)
+    }
+
+    /// Detaches the guard from the reference it was locked through.
+    pub fn into_owned(self) -> OwnedReading<T>
+    {
+        self.invariant();
+        let raw_ref = self.0;
+        std::mem::forget(self);
+        Reading(raw_ref, PhantomData)
+    }
+
+    /// `None` unless the referent was allocated with [`Strong::pin`].
+    pub fn as_pin_ref(&self) -> Option<Pin<&T>>
+    {
+        if self.0.account().flags() & tracking::PINNED == 0 {
+            return None;
+        }
+        Some(unsafe { Pin::new_unchecked(&**self) })
+    }
+
+    /// Promotes the sole reader to a writer without releasing the lock.
+    /// Hands the guard back if other readers exist.
+    pub fn try_upgrade(self) -> Result<Writing<'a, T>, Self>
+    {
+        self.invariant();
+        let account = self.0.account();
+        if account.try_upgrade() {
+            let raw_ref = self.0;
+            std::mem::forget(self);
+            Writing::<T>::check_order(&account);
+            Ok(Writing(raw_ref, PhantomData))
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl<'a, T: std::fmt::Debug + ?Sized> std::fmt::Debug for Reading<'a, T>
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        debug_reference("Reading", &self.0, Some(self), f)
+    }
 }
 
-impl<'a, T> Deref for Reading<'a, T>
+impl<'a, T: ?Sized> Deref for Reading<'a, T>
 {
     type Target = T;
 
     fn deref(&self) -> &Self::Target { unsafe { self.0.pointer().as_ptr().as_ref() } }
 }
 
-impl<'a, T> Drop for Reading<'a, T>
+impl<'a, T: ?Sized> Drop for Reading<'a, T>
 {
     fn drop(&mut self)
     {
         unsafe {
-            self.0.try_consume_shared();
+            self.0.account().unlock_shared();
         }
+        drop_queue::purge();
     }
 }
 
-impl<'a, T> Clone for Reading<'a, T>
+impl<'a, T: PartialEq> PartialEq<T> for Reading<'a, T>
+{
+    fn eq(&self, other: &T) -> bool { **self == *other }
+}
+
+impl<'a, 'b, T: PartialEq> PartialEq<Reading<'b, T>> for Reading<'a, T>
+{
+    fn eq(&self, other: &Reading<'b, T>) -> bool { **self == **other }
+}
+
+impl<'a, T: ?Sized> Clone for Reading<'a, T>
 {
     fn clone(&self) -> Self
     {
         if !self.0.account().try_lock_shared() {
             panic!()
         }
-        Self(self.0.clone(), PhantomData)
+        Self(self.0, PhantomData)
     }
 }
 
 #[repr(transparent)]
-pub struct Writing<'a, T>(RawRef<T>, PhantomData<&'a ()>);
+pub struct Writing<'a, T: ?Sized>(RawRef<T>, PhantomData<&'a ()>);
+
+/// See [`MappedReading`].
+pub type MappedWriting<'a, T> = Writing<'a, T>;
 
-impl<'a, T> Writing<'a, T>
+/// See [`OwnedReading`].
+pub type OwnedWriting<T> = Writing<'static, T>;
+
+impl<'a, T: ?Sized> Writing<'a, T>
 {
     fn invariant(&self) { self.0.invariant(); }
 
     pub(crate) fn try_new(raw_ref: RawRef<T>) -> Option<Self>
+    {
+        Self::try_new_with(raw_ref, AccountEnum::try_lock_exclusive)
+    }
+
+    pub(crate) fn try_new_until(raw_ref: RawRef<T>, deadline: Instant) -> Option<Self>
+    {
+        Self::try_new_with(raw_ref, |account| {
+            account.try_lock_exclusive_until(deadline)
+        })
+    }
+
+    /// `None` on frozen accounts, whose shared lock is never released
+    pub(crate) fn new_blocking(raw_ref: RawRef<T>) -> Option<Self>
+    {
+        Self::try_new_with(raw_ref, |account| {
+            if account.flags() & tracking::FROZEN != 0 {
+                return false;
+            }
+            account.lock_exclusive();
+            true
+        })
+    }
+
+    fn try_new_with<F>(raw_ref: RawRef<T>, lock: F) -> Option<Self>
+    where
+        F: FnOnce(&AccountEnum) -> bool,
     {
         raw_ref.invariant();
-        if raw_ref.account().try_lock_exclusive() {
+        let account = raw_ref.account();
+        if lock(&account) {
+            if !raw_ref.is_valid() {
+                unsafe { account.unlock_exclusive() };
+                return None;
+            }
+            Self::check_order(&account);
             let res = Self(raw_ref, PhantomData);
             res.invariant();
             Some(res)
         } else {
+            if config::reentrant_panic_enabled() {
+                if let AccountEnum::Local(l) = account {
+                    if l.is_locked_exclusive() {
+                        panic!("re-entrant exclusive borrow of a thread-local reference")
+                    }
+                }
+            }
             None
         }
     }
+
+    // assumes exclusive lock, which is released before panicking
+    fn check_order(account: &AccountEnum)
+    {
+        if !config::lock_order_check_enabled() {
+            return;
+        }
+        let id = account.allocation_id();
+        if let Err(held) = tracking::hold_exclusive(id) {
+            unsafe { account.unlock_exclusive() };
+            panic!("lock order violation: exclusive lock on {id} taken while holding {held}")
+        }
+    }
+}
+
+impl<'a, T: ?Sized> Writing<'a, T>
+{
+    /// Narrows the guard to part of the referent, keeping the same lock.
+    pub fn map<U, F>(mut self, f: F) -> MappedWriting<'a, U>
+    where
+        U: ?Sized,
+        for<'b> F: FnOnce(&'b mut T) -> &'b mut U,
+    {
+        self.invariant();
+        let target = NonNull::from(f(&mut self));
+        let raw_ref = self.0;
+        std::mem::forget(self);
+        Writing(raw_ref.map(|_| target), PhantomData)
+    }
+
+    /// Detaches the guard from the reference it was locked through.
+    pub fn into_owned(self) -> OwnedWriting<T>
+    {
+        self.invariant();
+        let raw_ref = self.0;
+        std::mem::forget(self);
+        Writing(raw_ref, PhantomData)
+    }
+
+    /// `None` unless the referent was allocated with [`Strong::pin`], in which
+    /// case this is the only mutable access there is.
+    pub fn as_pin_mut(&mut self) -> Option<Pin<&mut T>>
+    {
+        if self.0.account().flags() & tracking::PINNED == 0 {
+            return None;
+        }
+        Some(unsafe { Pin::new_unchecked(self.0.pointer().as_ptr().as_mut()) })
+    }
+
+    /// Trades the exclusive lock for a shared one without ever releasing it.
+    pub fn downgrade(self) -> Reading<'a, T>
+    {
+        self.invariant();
+        let raw_ref = self.0;
+        std::mem::forget(self);
+        let account = raw_ref.account();
+        tracking::release_exclusive(account.allocation_id());
+        unsafe { account.downgrade() };
+        Reading(raw_ref, PhantomData)
+    }
+
+    /// Volatile counterpart of [`Reading::read_volatile`].
+    pub fn read_volatile(&self) -> T
+    where
+        T: Copy,
+    {
+        unsafe { std::ptr::read_volatile(self.0.pointer().as_ptr().as_ptr()) }
+    }
+
+    /// Volatile write of the whole referent. See [`Reading::read_volatile`]
+    /// for the layout requirements.
+    pub fn write_volatile(&mut self, value: T)
+    where
+        T: Copy,
+    {
+        unsafe { std::ptr::write_volatile(self.0.pointer().as_ptr().as_ptr(), value) }
+    }
+
+    pub fn replace(&mut self, value: T) -> T
+    where
+        T: Sized,
+    {
+        std::mem::replace(&mut **self, value)
+    }
+
+    pub fn set(&mut self, value: T)
+    where
+        T: Sized,
+    {
+        **self = value
+    }
+
+    pub fn take(&mut self) -> T
+    where
+        T: Default,
+    {
+        std::mem::take(&mut **self)
+    }
+}
+
+impl<'a> std::fmt::Write for Writing<'a, String>
+{
+    fn write_str(&mut self, s: &str) -> std::fmt::Result { (**self).write_str(s) }
+
+    fn write_char(&mut self, c: char) -> std::fmt::Result { (**self).write_char(c) }
+
+    fn write_fmt(&mut self, args: std::fmt::Arguments<'_>) -> std::fmt::Result
+    {
+        (**self).write_fmt(args)
+    }
+}
+
+impl<'a, T: std::fmt::Debug + ?Sized> std::fmt::Debug for Writing<'a, T>
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        debug_reference("Writing", &self.0, Some(self), f)
+    }
 }
 
-impl<'a, T> Deref for Writing<'a, T>
+impl<'a, T: ?Sized> Deref for Writing<'a, T>
 {
     type Target = T;
 
     fn deref(&self) -> &Self::Target { unsafe { self.0.pointer().as_ptr().as_ref() } }
 }
 
-impl<'a, T> DerefMut for Writing<'a, T>
+impl<'a, T: ?Sized> DerefMut for Writing<'a, T>
+{
+    fn deref_mut(&mut self) -> &mut Self::Target
+    {
+        if self.0.account().flags() & tracking::PINNED != 0 {
+            panic!("{}", PINNED)
+        }
+        unsafe { self.0.pointer().as_ptr().as_mut() }
+    }
+}
+
+impl<'a, T: PartialEq> PartialEq<T> for Writing<'a, T>
+{
+    fn eq(&self, other: &T) -> bool { **self == *other }
+}
+
+impl<'a, 'b, T: PartialEq> PartialEq<Writing<'b, T>> for Writing<'a, T>
 {
-    fn deref_mut(&mut self) -> &mut Self::Target { unsafe { self.0.pointer().as_ptr().as_mut() } }
+    fn eq(&self, other: &Writing<'b, T>) -> bool { **self == **other }
 }
 
-impl<'a, T> Drop for Writing<'a, T>
+impl<'a, T: ?Sized> Drop for Writing<'a, T>
 {
     fn drop(&mut self)
     {
+        let account = self.0.account();
+        tracking::release_exclusive(account.allocation_id());
         unsafe {
-            self.0.try_consume_exclusive();
+            account.unlock_exclusive();
+        }
+        drop_queue::purge();
+    }
+}
+
+/// Lazily initialized, generationally tracked value.
+///
+/// The cell owns the `Strong` created on first access; aliases handed out
+/// afterwards stay valid for as long as the cell lives.
+pub struct GenLazy<T, F = fn() -> T>
+{
+    cell: OnceCell<Strong<T>>,
+    init: Cell<Option<F>>,
+}
+
+impl<T, F> GenLazy<T, F>
+where
+    F: FnOnce() -> T,
+{
+    pub const fn new(init: F) -> Self
+    {
+        Self {
+            cell: OnceCell::new(),
+            init: Cell::new(Some(init)),
         }
     }
+
+    fn force(&self) -> &Strong<T>
+    {
+        self.cell.get_or_init(|| match self.init.take() {
+            Some(f) => Strong::new(f()),
+            None => panic!("GenLazy initializer panicked on a previous access"),
+        })
+    }
+
+    pub fn get(&self) -> Option<Reading<'_, T>> { self.force().try_read() }
+
+    pub fn alias(&self) -> Weak<T> { self.force().alias() }
+}
+
+/// Exclusive access with a snapshot to roll back to. Dropping the guard
+/// without calling [`TransactionGuard::rollback`] keeps the changes.
+pub struct TransactionGuard<'a, T>
+{
+    writing: Writing<'a, T>,
+    snapshot: T,
+}
+
+impl<'a, T> TransactionGuard<'a, T>
+{
+    pub fn commit(self) {}
+
+    /// Restores the snapshot in place. Pinned referents never get here, as
+    /// [`Strong::begin_write`] needs the owner by `&mut`, which
+    /// [`PinnedStrong`] never lends out.
+    pub fn rollback(self)
+    {
+        let Self {
+            mut writing,
+            snapshot,
+        } = self;
+        *writing = snapshot;
+    }
+}
+
+impl<'a, T> Deref for TransactionGuard<'a, T>
+{
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target { &self.writing }
+}
+
+impl<'a, T> DerefMut for TransactionGuard<'a, T>
+{
+    fn deref_mut(&mut self) -> &mut Self::Target { &mut self.writing }
 }
 
+/// Strong reference on its way to another thread. See [`Strong::send`].
+///
+/// Aliases left behind may still read the referent while the receiving thread
+/// does, hence the `Sync` bound.
 #[repr(transparent)]
-pub struct Sendable<T>(Strong<T>);
+pub struct Sendable<T: ?Sized>(Strong<T>);
+
+unsafe impl<T: Send + Sync + ?Sized> Send for Sendable<T> {}
+
+impl<T: ?Sized> Sendable<T>
+{
+    pub fn into_inner(self) -> Strong<T> { self.0 }
+}
 
+/// Weak reference usable from any thread, for instance from a `static`. See
+/// [`Weak::share`].
 #[repr(transparent)]
-pub struct Shareable<T>(Weak<T>);
+pub struct Shareable<T: ?Sized>(Weak<T>);
+
+unsafe impl<T: Send + Sync + ?Sized> Send for Shareable<T> {}
+
+unsafe impl<T: Send + Sync + ?Sized> Sync for Shareable<T> {}
+
+impl<T: ?Sized> Clone for Shareable<T>
+{
+    fn clone(&self) -> Self { Self(self.0.clone()) }
+}
+
+impl<T: ?Sized> Deref for Shareable<T>
+{
+    type Target = Weak<T>;
+
+    fn deref(&self) -> &Weak<T> { &self.0 }
+}
+
+impl<T: ?Sized> Shareable<T>
+{
+    pub fn into_inner(self) -> Weak<T> { self.0 }
+}
 
+/// Strong or weak reference on its way to another thread. See
+/// [`GenRef::transfer`].
 #[repr(transparent)]
-pub struct Transferrable<T>(GenRef<T>);
+pub struct Transferrable<T: ?Sized>(GenRef<T>);
+
+unsafe impl<T: Send + Sync + ?Sized> Send for Transferrable<T> {}
+
+impl<T: ?Sized> Transferrable<T>
+{
+    pub fn into_inner(self) -> GenRef<T> { self.0 }
+
+    pub fn into_enum(self) -> TransferrableEnum<T>
+    {
+        match self.0.into_enum() {
+            GenRefEnum::Weak(weak) => TransferrableEnum::Shareable(Shareable(weak)),
+            GenRefEnum::Strong(strong) => TransferrableEnum::Sendable(Sendable(strong)),
+        }
+    }
+}
+
+impl<T: ?Sized> From<TransferrableEnum<T>> for Transferrable<T>
+{
+    fn from(it: TransferrableEnum<T>) -> Self
+    {
+        match it {
+            TransferrableEnum::Sendable(Sendable(strong)) => Self(strong.into()),
+            TransferrableEnum::Shareable(Shareable(weak)) => Self(weak.into()),
+        }
+    }
+}
 
-pub enum TransferrableEnum<T>
+pub enum TransferrableEnum<T: ?Sized>
 {
     Sendable(Sendable<T>),
     Shareable(Shareable<T>),
@@ -1,8 +1,12 @@
 use super::global_ledger::*;
-use super::{tracking::Tracking, *};
+use super::{
+    tracking::{self, Tracking},
+    *,
+};
 use std::{
     cell::{Cell, Ref, RefCell},
     ptr::NonNull,
+    time::Instant,
 };
 
 #[repr(transparent)]
@@ -11,7 +15,28 @@ pub(crate) struct LocalIndex(NonNull<RefCell<LocalAccount>>);
 
 impl LocalIndex
 {
-    fn borrow(&self) -> Ref<LocalAccount> { unsafe { self.0.as_ref() }.borrow() }
+    fn borrow(&self) -> Ref<'_, LocalAccount> { unsafe { self.0.as_ref() }.borrow() }
+
+    pub(crate) fn address(&self) -> usize { self.0.as_ptr() as usize }
+
+    pub(crate) fn is_sharable(&self) -> bool { matches!(&*self.borrow(), LocalAccount::Global(_)) }
+
+    pub(crate) fn global(&self) -> Option<GlobalIndex>
+    {
+        match &*self.borrow() {
+            LocalAccount::Local(_) => None,
+            LocalAccount::Global(g) => Some(*g),
+        }
+    }
+
+    /// only thread-local counters, which can only be locked by this thread
+    pub(crate) fn is_locked_exclusive(&self) -> bool
+    {
+        match &*self.borrow() {
+            LocalAccount::Local(l) => l.lock.get() < 0,
+            LocalAccount::Global(_) => false,
+        }
+    }
 
     // assumes exclusive lock
     pub(crate) unsafe fn make_sharable(&self) -> GlobalIndex
@@ -20,10 +45,11 @@ impl LocalIndex
         let res: GlobalIndex;
         let acc = LocalAccount::Global(match &*cell {
             LocalAccount::Local(l) => {
-                res = global_ledger::allocate();
+                res = global_ledger::allocate_continuing(l.generation.get(), l.published.get());
                 if !res.try_lock_exclusive() {
                     panic!("failed to exclusive lock just-allocated global index")
                 }
+                res.set_allocation_id(l.allocation_id());
                 res
             }
             LocalAccount::Global(g) => {
@@ -43,9 +69,25 @@ impl Tracking for LocalIndex
     fn try_lock_exclusive(&self) -> bool { self.borrow().try_lock_exclusive() }
     fn lock_exclusive(&self) { self.borrow().lock_exclusive() }
     fn try_lock_shared(&self) -> bool { self.borrow().try_lock_shared() }
+    fn lock_shared(&self) { self.borrow().lock_shared() }
+    fn try_lock_exclusive_until(&self, deadline: Instant) -> bool
+    {
+        self.borrow().try_lock_exclusive_until(deadline)
+    }
+    fn try_lock_shared_until(&self, deadline: Instant) -> bool
+    {
+        self.borrow().try_lock_shared_until(deadline)
+    }
     fn try_upgrade(&self) -> bool { self.borrow().try_upgrade() }
+    unsafe fn downgrade(&self) { self.borrow().downgrade() }
     unsafe fn unlock_exclusive(&self) { self.borrow().unlock_exclusive() }
     unsafe fn unlock_shared(&self) { self.borrow().unlock_shared() }
+    fn flags(&self) -> u64 { self.borrow().flags() }
+    fn raise_flags(&self, flags: u64) { self.borrow().raise_flags(flags) }
+    fn allocation_id(&self) -> u64 { self.borrow().allocation_id() }
+    fn set_allocation_id(&self, id: u64) { self.borrow().set_allocation_id(id) }
+    fn publish(&self) -> u64 { self.borrow().publish() }
+    fn published(&self) -> u64 { self.borrow().published() }
 }
 
 #[derive(Debug, Clone)]
@@ -97,6 +139,30 @@ impl Tracking for LocalAccount
         }
     }
 
+    fn lock_shared(&self)
+    {
+        match self {
+            Self::Local(l) => l.lock_shared(),
+            Self::Global(g) => g.lock_shared(),
+        }
+    }
+
+    fn try_lock_exclusive_until(&self, deadline: Instant) -> bool
+    {
+        match self {
+            Self::Local(l) => l.try_lock_exclusive_until(deadline),
+            Self::Global(g) => g.try_lock_exclusive_until(deadline),
+        }
+    }
+
+    fn try_lock_shared_until(&self, deadline: Instant) -> bool
+    {
+        match self {
+            Self::Local(l) => l.try_lock_shared_until(deadline),
+            Self::Global(g) => g.try_lock_shared_until(deadline),
+        }
+    }
+
     fn try_upgrade(&self) -> bool
     {
         match self {
@@ -105,6 +171,14 @@ impl Tracking for LocalAccount
         }
     }
 
+    unsafe fn downgrade(&self)
+    {
+        match self {
+            Self::Local(l) => l.downgrade(),
+            Self::Global(g) => g.downgrade(),
+        }
+    }
+
     unsafe fn unlock_exclusive(&self)
     {
         match self {
@@ -120,6 +194,54 @@ impl Tracking for LocalAccount
             Self::Global(g) => g.unlock_shared(),
         }
     }
+
+    fn flags(&self) -> u64
+    {
+        match self {
+            Self::Local(l) => l.flags(),
+            Self::Global(g) => g.flags(),
+        }
+    }
+
+    fn raise_flags(&self, flags: u64)
+    {
+        match self {
+            Self::Local(l) => l.raise_flags(flags),
+            Self::Global(g) => g.raise_flags(flags),
+        }
+    }
+
+    fn allocation_id(&self) -> u64
+    {
+        match self {
+            Self::Local(l) => l.allocation_id(),
+            Self::Global(g) => g.allocation_id(),
+        }
+    }
+
+    fn set_allocation_id(&self, id: u64)
+    {
+        match self {
+            Self::Local(l) => l.set_allocation_id(id),
+            Self::Global(g) => g.set_allocation_id(id),
+        }
+    }
+
+    fn publish(&self) -> u64
+    {
+        match self {
+            Self::Local(l) => l.publish(),
+            Self::Global(g) => g.publish(),
+        }
+    }
+
+    fn published(&self) -> u64
+    {
+        match self {
+            Self::Local(l) => l.published(),
+            Self::Global(g) => g.published(),
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -127,6 +249,8 @@ pub(crate) struct LocalCounter
 {
     lock: Cell<i32>,
     generation: Cell<u64>,
+    allocation_id: Cell<u64>,
+    published: Cell<u64>,
 }
 
 impl Tracking for LocalCounter
@@ -135,18 +259,18 @@ impl Tracking for LocalCounter
 
     fn invalidate(&self) -> u64
     {
-        let current = self.generation.get();
+        let current = self.generation();
         self.generation.set(current + 1);
-        current & RawRef::<()>::COUNTER_MASK
+        current
     }
 
     fn try_lock_exclusive(&self) -> bool
     {
         if self.lock.get() == 0 {
             self.lock.set(-1);
-            return true;
+            true
         } else {
-            return false;
+            false
         }
     }
 
@@ -161,20 +285,40 @@ impl Tracking for LocalCounter
     {
         if self.lock.get() >= 0 {
             self.lock.set(self.lock.get() + 1);
-            return true;
+            true
         } else {
-            return false;
+            false
+        }
+    }
+
+    fn lock_shared(&self)
+    {
+        if !self.try_lock_shared() {
+            panic!("unconditional locking operation on locked local counter")
         }
     }
 
+    // nothing can release a thread-local lock while this thread waits
+    fn try_lock_exclusive_until(&self, _deadline: Instant) -> bool { self.try_lock_exclusive() }
+
+    fn try_lock_shared_until(&self, _deadline: Instant) -> bool { self.try_lock_shared() }
+
     fn try_upgrade(&self) -> bool
     {
         if self.lock.get() == 1 {
             self.lock.set(-1);
-            return true;
+            true
         } else {
-            return false;
+            false
+        }
+    }
+
+    unsafe fn downgrade(&self)
+    {
+        if self.lock.get() >= 0 {
+            panic!("downgrade on local tracker not exclusively locked");
         }
+        self.lock.set(1);
     }
 
     unsafe fn unlock_exclusive(&self)
@@ -196,23 +340,55 @@ impl Tracking for LocalCounter
         }
         self.lock.set(self.lock.get() - 1);
     }
+
+    fn flags(&self) -> u64 { self.generation.get() & !RawRef::<()>::COUNTER_MASK }
+
+    fn raise_flags(&self, flags: u64)
+    {
+        self.generation
+            .set(self.generation.get() | (flags & !RawRef::<()>::COUNTER_MASK));
+    }
+
+    fn allocation_id(&self) -> u64 { self.allocation_id.get() }
+
+    fn set_allocation_id(&self, id: u64) { self.allocation_id.set(id) }
+
+    fn publish(&self) -> u64
+    {
+        self.published.set(self.published.get() + 1);
+        self.published.get()
+    }
+
+    fn published(&self) -> u64 { self.published.get() }
 }
 
 use bumpalo::Bump;
 thread_local! {
     static ARENA : RefCell<Bump> = RefCell::new(Bump::new());
-    static FREE_LIST : RefCell<Vec<LocalIndex>> = RefCell::new(Vec::new());
+    static FREE_LIST : RefCell<Vec<LocalIndex>> = const { RefCell::new(Vec::new()) };
+    static ALLOCATED : Cell<usize> = const { Cell::new(0) };
+    static RETIRED : Cell<usize> = const { Cell::new(0) };
 }
 
-pub(crate) fn allocate() -> LocalIndex { recycle().unwrap_or_else(fresh) }
+pub(crate) fn allocate() -> LocalIndex
+{
+    let res = recycle().unwrap_or_else(fresh);
+    let id = tracking::next_allocation_id();
+    res.set_allocation_id(id);
+    testing::allocated(id);
+    res
+}
 
 fn fresh() -> LocalIndex
 {
+    ALLOCATED.set(ALLOCATED.get() + 1);
     ARENA.with_borrow_mut(|arena| {
         LocalIndex(NonNull::from(arena.alloc(RefCell::new(
             LocalAccount::Local(LocalCounter {
                 lock: 0.into(),
                 generation: RawRef::<()>::COUNTER_INIT.into(),
+                allocation_id: 0.into(),
+                published: 0.into(),
             }),
         ))))
     })
@@ -224,5 +400,116 @@ pub(crate) unsafe fn free(li: LocalIndex)
 {
     li.invalidate();
     li.unlock_exclusive();
-    FREE_LIST.with_borrow_mut(|vec| vec.push(li))
+    let exhausted = li.generation() == RawRef::<()>::COUNTER_MASK;
+    if ISOLATED.get() && !ARENA.with_borrow_mut(|arena| owns(arena, li)) {
+        free_foreign(li, exhausted)
+    } else if exhausted {
+        RETIRED.set(RETIRED.get() + 1)
+    } else {
+        FREE_LIST.with_borrow_mut(|vec| vec.push(li))
+    }
+}
+
+struct Pool
+{
+    arena: Bump,
+    free_list: Vec<LocalIndex>,
+    allocated: usize,
+    retired: usize,
+}
+
+thread_local! {
+    static SAVED_POOLS : RefCell<Vec<Pool>> = const { RefCell::new(Vec::new()) };
+    static ISOLATED : Cell<bool> = const { Cell::new(false) };
+    // accounts still live when their isolated pool was left
+    static ESCAPED : Cell<usize> = const { Cell::new(0) };
+}
+
+// frees only need to check which pool an account belongs to while isolated
+// pools are active or accounts from finished ones are still live
+fn update_isolated()
+{
+    let depth = SAVED_POOLS.with_borrow(Vec::len);
+    ISOLATED.set(depth != 0 || ESCAPED.get() != 0)
+}
+
+#[cfg(test)]
+pub(crate) fn isolated() -> bool { ISOLATED.get() }
+
+fn owns(arena: &mut Bump, li: LocalIndex) -> bool
+{
+    arena.iter_allocated_chunks().any(|chunk| {
+        let start = chunk.as_ptr() as usize;
+        (start..start + chunk.len()).contains(&li.address())
+    })
+}
+
+// returns a freed account to the pool it was allocated from, unless that
+// pool was isolated and is gone, in which case the account is abandoned
+fn free_foreign(li: LocalIndex, exhausted: bool)
+{
+    let abandoned = SAVED_POOLS.with_borrow_mut(|pools| {
+        let owner = pools
+            .iter_mut()
+            .rev()
+            .find_map(|p| owns(&mut p.arena, li).then_some(p));
+        match owner {
+            Some(pool) if exhausted => pool.retired += 1,
+            Some(pool) => pool.free_list.push(li),
+            None => return true,
+        }
+        false
+    });
+    if abandoned {
+        ESCAPED.set(ESCAPED.get() - 1);
+        update_isolated()
+    }
+}
+
+struct Restore;
+
+impl Drop for Restore
+{
+    fn drop(&mut self)
+    {
+        ESCAPED.set(ESCAPED.get() + efficiency().live);
+        let pool = SAVED_POOLS
+            .with_borrow_mut(Vec::pop)
+            .expect("isolated pool stack underflow");
+        // accounts minted in isolation may still be referenced, so the
+        // isolated arena is leaked rather than freed
+        std::mem::forget(ARENA.replace(pool.arena));
+        FREE_LIST.set(pool.free_list);
+        ALLOCATED.set(pool.allocated);
+        RETIRED.set(pool.retired);
+        update_isolated()
+    }
+}
+
+pub(crate) fn with_isolated_pool<F: FnOnce() -> R, R>(f: F) -> R
+{
+    ISOLATED.set(true);
+    SAVED_POOLS.with_borrow_mut(|pools| {
+        pools.push(Pool {
+            arena: ARENA.replace(Bump::new()),
+            free_list: FREE_LIST.take(),
+            allocated: ALLOCATED.replace(0),
+            retired: RETIRED.replace(0),
+        })
+    });
+    let _restore = Restore;
+    f()
+}
+
+pub(crate) fn efficiency() -> CounterEfficiency
+{
+    let allocated = ALLOCATED.get();
+    let free_listed = FREE_LIST.with_borrow(Vec::len);
+    let retired = RETIRED.get();
+    CounterEfficiency {
+        allocated,
+        free_listed,
+        live: allocated - free_listed - retired,
+        retired,
+    }
 }
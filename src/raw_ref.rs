@@ -1,6 +1,9 @@
-use std::{mem, num::NonZeroU64, ptr::NonNull};
+use std::{marker::Unsize, mem, num::NonZeroU64, ops::CoerceUnsized, ptr::NonNull};
 
-use crate::tracking::{self, Account, AccountEnum};
+use crate::{
+    drop_queue,
+    tracking::{self, Account, AccountEnum},
+};
 
 use super::{
     global_ledger::GlobalIndex,
@@ -8,24 +11,20 @@ use super::{
     tracking::*,
 };
 
-pub(crate) enum PointerEnum<T>
+pub(crate) enum PointerEnum<T: ?Sized>
 {
     Weak(NonNull<T>),
     Strong(NonNull<T>),
 }
 
-impl<T> Clone for PointerEnum<T>
+impl<T: ?Sized> Clone for PointerEnum<T>
 {
-    fn clone(&self) -> Self
-    {
-        match self {
-            Self::Weak(arg0) => Self::Weak(arg0.clone()),
-            Self::Strong(arg0) => Self::Strong(arg0.clone()),
-        }
-    }
+    fn clone(&self) -> Self { *self }
 }
 
-impl<T> std::fmt::Debug for PointerEnum<T>
+impl<T: ?Sized> Copy for PointerEnum<T> {}
+
+impl<T: ?Sized> std::fmt::Debug for PointerEnum<T>
 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
     {
@@ -36,7 +35,7 @@ impl<T> std::fmt::Debug for PointerEnum<T>
     }
 }
 
-impl<T> PointerEnum<T>
+impl<T: ?Sized> PointerEnum<T>
 {
     pub(crate) fn as_ptr(self) -> NonNull<T>
     {
@@ -48,6 +47,7 @@ impl<T> PointerEnum<T>
 
     pub(crate) fn map<F, U>(self, f: F) -> PointerEnum<U>
     where
+        U: ?Sized,
         F: FnOnce(NonNull<T>) -> NonNull<U>,
     {
         match self {
@@ -58,26 +58,23 @@ impl<T> PointerEnum<T>
 }
 
 #[repr(C)]
-pub(crate) struct RawRef<T>
+pub(crate) struct RawRef<T: ?Sized>
 {
     account: Account,
     pointer: NonNull<T>,
     generation: NonZeroU64,
 }
 
-impl<T> Clone for RawRef<T>
+impl<T: ?Sized> Clone for RawRef<T>
 {
-    fn clone(&self) -> Self
-    {
-        Self {
-            account: self.account.clone(),
-            pointer: self.pointer.clone(),
-            generation: self.generation.clone(),
-        }
-    }
+    fn clone(&self) -> Self { *self }
 }
 
-impl<T> RawRef<T>
+impl<T: ?Sized> Copy for RawRef<T> {}
+
+impl<T: ?Sized + Unsize<U>, U: ?Sized> CoerceUnsized<RawRef<U>> for RawRef<T> {}
+
+impl<T: ?Sized> RawRef<T>
 {
     #[cfg(test)]
     pub(crate) fn invariant(&self)
@@ -96,6 +93,23 @@ impl<T> RawRef<T>
     #[cfg(not(test))]
     pub(crate) fn invariant(&self) {}
 
+    pub(crate) const fn dangling() -> Self
+    where
+        T: Sized,
+    {
+        let generation = Self::GLOBAL_ACCOUNT | Self::WEAK_REFERENCE | Self::COUNTER_INIT;
+        Self {
+            account: Account {
+                global: GlobalIndex::dead(),
+            },
+            pointer: NonNull::dangling(),
+            generation: match NonZeroU64::new(generation) {
+                Some(generation) => generation,
+                None => unreachable!(),
+            },
+        }
+    }
+
     fn new_from_parts(acc: AccountEnum, ptr: PointerEnum<T>) -> Self
     {
         let (account, acc_flag) = match acc {
@@ -150,8 +164,71 @@ impl<T> RawRef<T>
         self.try_consume(AccountEnum::try_upgrade)
     }
 
+    fn owned_locking_primitive(&self) -> fn(&AccountEnum) -> bool
+    {
+        if self.account().flags() & tracking::FROZEN != 0 {
+            AccountEnum::try_upgrade
+        } else {
+            AccountEnum::try_lock_exclusive
+        }
+    }
+
+    pub(crate) unsafe fn try_consume_owned(&self) -> Option<Box<T>>
+    {
+        self.try_consume(self.owned_locking_primitive())
+    }
+
+    /// blocks on global accounts, panics on locked local ones
+    pub(crate) unsafe fn consume_blocking(&self) -> Box<T>
+    {
+        if self.account().flags() & tracking::FROZEN != 0 {
+            return self
+                .try_consume_shared()
+                .expect("blocking consumption of frozen reference with readers");
+        }
+        self.try_consume(|account| {
+            account.lock_exclusive();
+            true
+        })
+        .unwrap()
+    }
+
+    /// assumes ownership
+    pub(crate) unsafe fn consume_or_defer<F>(&self, f: F)
+    where
+        F: FnOnce(Box<T>),
+    {
+        let locking_primitive = self.owned_locking_primitive();
+        if let Some(b) = self.try_consume(locking_primitive) {
+            f(b)
+        } else {
+            let ptr = self.pointer().as_ptr();
+            drop_queue::defer(
+                self.account(),
+                locking_primitive,
+                Box::new(move || f(Box::from_raw(ptr.as_ptr()))),
+            )
+        }
+    }
+
+    pub(crate) fn try_freeze(&self) -> bool
+    {
+        self.invariant();
+        let account = self.account();
+        if account.flags() & tracking::FROZEN != 0 {
+            return true;
+        }
+        if account.try_lock_shared() {
+            account.raise_flags(tracking::FROZEN);
+            true
+        } else {
+            false
+        }
+    }
+
     pub(crate) fn map<F, U>(self, f: F) -> RawRef<U>
     where
+        U: ?Sized,
         F: FnOnce(NonNull<T>) -> NonNull<U>,
     {
         let res = RawRef::new_from_parts(self.account(), self.pointer().map(f));
@@ -182,6 +259,8 @@ impl<T> RawRef<T>
     pub(crate) fn set_weak(mut self) -> Self
     {
         self.invariant();
+        // a weak reference to this generation may exist from here on
+        self.account().raise_flags(tracking::ALIASED);
         self.generation =
             NonZeroU64::new((self.generation.get() & !Self::REFERENCE_MASK) | Self::WEAK_REFERENCE)
                 .unwrap();
@@ -189,6 +268,49 @@ impl<T> RawRef<T>
         self
     }
 
+    pub(crate) fn set_strong(mut self) -> Self
+    {
+        self.invariant();
+        self.generation = NonZeroU64::new(
+            (self.generation.get() & !Self::REFERENCE_MASK) | Self::STRONG_REFERENCE,
+        )
+        .unwrap();
+        self.invariant();
+        self
+    }
+
+    /// moves a thread-local account over to the global ledger, keeping its
+    /// generation so that aliases stay valid; panics if it is locked
+    ///
+    /// Invalid references are pointed at the dead account instead, as their
+    /// thread-local account may since have been reused.
+    pub(crate) fn globalize(self) -> Self
+    {
+        self.invariant();
+        let AccountEnum::Local(li) = self.account() else {
+            return self;
+        };
+        let gi = if !self.is_valid() {
+            GlobalIndex::dead()
+        } else if li.is_sharable() {
+            unsafe { li.make_sharable() }
+        } else {
+            if !li.try_lock_exclusive() {
+                panic!("cannot globalize a borrowed thread-local reference")
+            }
+            let gi = unsafe { li.make_sharable() };
+            unsafe { gi.unlock_exclusive() };
+            gi
+        };
+        let res = Self {
+            account: Account { global: gi },
+            ..self
+        }
+        .set_global();
+        res.invariant();
+        res
+    }
+
     fn set_global(mut self) -> Self
     {
         self.invariant();
@@ -199,7 +321,17 @@ impl<T> RawRef<T>
         self
     }
 
-    fn counter(self) -> u64 { self.generation.get() & Self::COUNTER_MASK }
+    pub(crate) fn counter(&self) -> u64 { self.generation.get() & Self::COUNTER_MASK }
+
+    pub(crate) fn is_valid(&self) -> bool { self.account().generation() == self.counter() }
+
+    /// same allocation and generation, regardless of pointer projections or
+    /// of whether either side was aliased before the account was shared
+    pub(crate) fn same_allocation<U: ?Sized>(&self, other: &RawRef<U>) -> bool
+    {
+        self.counter() == other.counter()
+            && self.account().allocation_id() == other.account().allocation_id()
+    }
 
     const FLAG_MASK: u64 = 0b1111u64.reverse_bits();
     pub(crate) const COUNTER_MASK: u64 = !Self::FLAG_MASK;
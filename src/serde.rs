@@ -0,0 +1,501 @@
+//! Serialization of reference graphs.
+//!
+//! Weak references serialize as ids assigned by a [`SerializeContext`] and
+//! deserialize as aliases of the strong references rebuilt by a
+//! [`DeserializeContext`]. A [`RefTable`] does both for a list of nodes, with
+//! each node's id being its position.
+
+use std::{
+    any::TypeId,
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    fmt,
+    marker::PhantomData,
+    mem::MaybeUninit,
+    ptr::NonNull,
+    rc::Rc,
+};
+
+use ::serde::{
+    de::{self, DeserializeSeed, MapAccess, SeqAccess, Visitor},
+    ser::{self, SerializeStruct},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use crate::{raw_ref::RawRef, tracking::Tracking, Strong, Weak};
+
+thread_local! {
+    static SERIALIZING : RefCell<Option<HashMap<u64, usize>>> = const { RefCell::new(None) };
+    static DESERIALIZING : RefCell<Option<(TypeId, Rc<dyn SlotSource>)>> = const { RefCell::new(None) };
+}
+
+/// Strong references serialized together, so that weak references between
+/// them survive a round trip.
+pub struct RefTable<T>(Vec<Strong<T>>);
+
+impl<T> RefTable<T>
+{
+    pub fn new(nodes: Vec<Strong<T>>) -> Self { Self(nodes) }
+
+    pub fn nodes(&self) -> &[Strong<T>] { &self.0 }
+
+    pub fn into_nodes(self) -> Vec<Strong<T>> { self.0 }
+}
+
+impl<T: Serialize> Serialize for RefTable<T>
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    {
+        let mut context = SerializeContext::new();
+        for node in &self.0 {
+            context.insert(node);
+        }
+        context.scope(|| {
+            let mut table = serializer.serialize_struct("RefTable", 2)?;
+            table.serialize_field("len", &self.0.len())?;
+            table.serialize_field("nodes", &self.0)?;
+            table.end()
+        })
+    }
+}
+
+/// Serializes the referent under a temporary shared lock, failing if it is
+/// write-locked.
+impl<T: Serialize> Serialize for Strong<T>
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    {
+        let reading = self
+            .try_read()
+            .ok_or_else(|| ser::Error::custom("strong reference is write-locked"))?;
+        reading.serialize(serializer)
+    }
+}
+
+/// Deserializes into a fresh allocation.
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Strong<T>
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error>
+    {
+        T::deserialize(deserializer).map(Strong::new)
+    }
+}
+
+/// Assigns ids to strong references, so that weak references to them can be
+/// serialized as those ids.
+#[derive(Default)]
+pub struct SerializeContext
+{
+    ids: HashMap<u64, usize>,
+}
+
+impl SerializeContext
+{
+    pub fn new() -> Self { Self::default() }
+
+    /// Ids are handed out in insertion order, starting from zero. Inserting
+    /// the same allocation again returns its existing id.
+    pub fn insert<T>(&mut self, strong: &Strong<T>) -> usize
+    {
+        let next = self.ids.len();
+        *self.ids.entry(strong.id()).or_insert(next)
+    }
+
+    /// Runs `f` with this context installed on the current thread. Weak
+    /// references serialized outside of any context, or to allocations not
+    /// inserted into it, fail to serialize.
+    pub fn scope<R, F: FnOnce() -> R>(&mut self, f: F) -> R
+    {
+        let outer = SERIALIZING.replace(Some(std::mem::take(&mut self.ids)));
+        let res = f();
+        self.ids = SERIALIZING.replace(outer).unwrap_or_default();
+        res
+    }
+}
+
+impl<T> Serialize for Weak<T>
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    {
+        let id = self
+            .source_id()
+            .ok_or_else(|| ser::Error::custom("stale weak reference"))?;
+        let index = SERIALIZING.with_borrow(|ids| ids.as_ref()?.get(&id).copied());
+        match index {
+            Some(index) => serializer.serialize_u64(index as u64),
+            None => Err(ser::Error::custom(
+                "weak reference outside of the serialize context",
+            )),
+        }
+    }
+}
+
+impl<'de, T: 'static> Deserialize<'de> for Weak<T>
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error>
+    {
+        let index = u64::deserialize(deserializer)? as usize;
+        DESERIALIZING.with_borrow(|slots| match slots {
+            Some((ty, slots)) if *ty == TypeId::of::<T>() => slots
+                .slot(index)
+                .map(|raw_ref| Weak::new(raw_ref.map(NonNull::cast)))
+                .ok_or_else(|| de::Error::custom("weak reference id out of range")),
+            _ => Err(de::Error::custom(
+                "weak reference outside of a deserialize context of its type",
+            )),
+        })
+    }
+}
+
+/// Rebuilds strong references for ids handed out by a [`SerializeContext`].
+///
+/// Allocations are made as their ids are first seen, whether by a weak
+/// reference deserialized inside [`DeserializeContext::scope`] or by filling
+/// in a value, so forward references and cycles resolve immediately while the
+/// declared length alone never allocates anything. The values are filled in
+/// in id order, and the allocations stay exclusively locked until then so
+/// weak references can never observe uninitialized memory.
+pub struct DeserializeContext<T>
+{
+    slots: Rc<Slots<T>>,
+    filled: usize,
+}
+
+struct Slots<T>
+{
+    len: usize,
+    strongs: RefCell<BTreeMap<usize, Strong<MaybeUninit<T>>>>,
+}
+
+// type-erased access to the slots of the installed context
+trait SlotSource
+{
+    fn slot(&self, index: usize) -> Option<RawRef<()>>;
+}
+
+impl<T> SlotSource for Slots<T>
+{
+    fn slot(&self, index: usize) -> Option<RawRef<()>>
+    {
+        if index >= self.len {
+            return None;
+        }
+        let mut strongs = self.strongs.borrow_mut();
+        let strong = strongs.entry(index).or_insert_with(|| {
+            let strong = Strong::new(MaybeUninit::uninit());
+            if !strong.0.account().try_lock_exclusive() {
+                panic!("failed to exclusive lock just-allocated strong reference")
+            }
+            strong
+        });
+        Some(strong.0.set_weak().map(NonNull::cast))
+    }
+}
+
+impl<T: 'static> DeserializeContext<T>
+{
+    pub fn new(len: usize) -> Self
+    {
+        Self {
+            slots: Rc::new(Slots {
+                len,
+                strongs: RefCell::new(BTreeMap::new()),
+            }),
+            filled: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize { self.slots.len }
+
+    pub fn is_empty(&self) -> bool { self.slots.len == 0 }
+
+    pub fn is_filled(&self) -> bool { self.filled == self.slots.len }
+
+    /// Runs `f` with this context installed on the current thread.
+    pub fn scope<R, F: FnOnce(&mut Self) -> R>(&mut self, f: F) -> R
+    {
+        let slots: Rc<dyn SlotSource> = self.slots.clone();
+        let outer = DESERIALIZING.replace(Some((TypeId::of::<T>(), slots)));
+        let res = f(self);
+        DESERIALIZING.set(outer);
+        res
+    }
+
+    /// Fills the next id. Panics if every id is already filled.
+    pub fn push(&mut self, value: T)
+    {
+        let raw_ref = self
+            .slots
+            .slot(self.filled)
+            .expect("every id is already filled");
+        let ptr = raw_ref.pointer().as_ptr().cast::<MaybeUninit<T>>();
+        unsafe { (*ptr.as_ptr()).write(value) };
+        self.filled += 1;
+    }
+
+    /// Hands the context back unless every id has been filled.
+    pub fn finish(mut self) -> Result<Vec<Strong<T>>, Self>
+    {
+        if !self.is_filled() {
+            return Err(self);
+        }
+        self.unlock();
+        self.filled = 0;
+        Ok(self
+            .slots
+            .strongs
+            .take()
+            .into_values()
+            .map(|strong| {
+                let res = Strong(strong.0.map(NonNull::cast));
+                std::mem::forget(strong);
+                res
+            })
+            .collect())
+    }
+}
+
+impl<T> DeserializeContext<T>
+{
+    fn unlock(&self)
+    {
+        for strong in self.slots.strongs.borrow().values() {
+            unsafe { strong.0.account().unlock_exclusive() }
+        }
+    }
+}
+
+impl<T> Drop for DeserializeContext<T>
+{
+    fn drop(&mut self)
+    {
+        self.unlock();
+        let mut strongs = self.slots.strongs.take();
+        for strong in strongs.range_mut(..self.filled).map(|(_, strong)| strong) {
+            unsafe { (*strong.0.pointer().as_ptr().as_ptr()).assume_init_drop() }
+        }
+    }
+}
+
+impl<'de, T: Deserialize<'de> + 'static> Deserialize<'de> for RefTable<T>
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error>
+    {
+        deserializer.deserialize_struct("RefTable", &["len", "nodes"], TableVisitor(PhantomData))
+    }
+}
+
+struct TableVisitor<T>(PhantomData<T>);
+
+impl<'de, T: Deserialize<'de> + 'static> Visitor<'de> for TableVisitor<T>
+{
+    type Value = RefTable<T>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result { f.write_str("a reference table") }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    {
+        let len: usize = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let mut context = DeserializeContext::new(len);
+        context
+            .scope(|context| seq.next_element_seed(NodesSeed(context)))?
+            .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        Ok(RefTable(context.finish().ok().expect(FILLED)))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error>
+    {
+        if map.next_key::<String>()?.as_deref() != Some("len") {
+            return Err(de::Error::custom("reference table length must come first"));
+        }
+        let len: usize = map.next_value()?;
+        if map.next_key::<String>()?.as_deref() != Some("nodes") {
+            return Err(de::Error::missing_field("nodes"));
+        }
+        let mut context = DeserializeContext::new(len);
+        context.scope(|context| map.next_value_seed(NodesSeed(context)))?;
+        Ok(RefTable(context.finish().ok().expect(FILLED)))
+    }
+}
+
+const FILLED: &str = "reference table nodes deserialized without filling every node";
+
+struct NodesSeed<'a, T>(&'a mut DeserializeContext<T>);
+
+impl<'a, 'de, T: Deserialize<'de> + 'static> DeserializeSeed<'de> for NodesSeed<'a, T>
+{
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'a, 'de, T: Deserialize<'de> + 'static> Visitor<'de> for NodesSeed<'a, T>
+{
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        write!(f, "a sequence of {} nodes", self.0.len())
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    {
+        while !self.0.is_filled() {
+            match seq.next_element()? {
+                Some(value) => self.0.push(value),
+                None => return Err(de::Error::invalid_length(self.0.filled, &self)),
+            }
+        }
+        if seq.next_element::<de::IgnoredAny>()?.is_some() {
+            return Err(de::Error::invalid_length(self.0.filled + 1, &self));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::{cell::Cell, rc::Rc};
+
+    use ::serde::{Deserialize, Serialize};
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize)]
+    struct Node
+    {
+        value: i32,
+        edges: Vec<Weak<Node>>,
+    }
+
+    fn node(value: i32) -> Strong<Node>
+    {
+        Strong::new(Node {
+            value,
+            edges: Vec::new(),
+        })
+    }
+
+    #[test]
+    fn ref_table_round_trips_cycles()
+    {
+        let (a, b) = (node(1), node(2));
+        a.try_write().unwrap().edges.push(b.alias());
+        b.try_write().unwrap().edges.extend([a.alias(), b.alias()]);
+        let json = serde_json::to_string(&RefTable::new(vec![a, b])).unwrap();
+        assert_eq!(
+            json,
+            r#"{"len":2,"nodes":[{"value":1,"edges":[1]},{"value":2,"edges":[0,1]}]}"#
+        );
+
+        let nodes = serde_json::from_str::<RefTable<Node>>(&json)
+            .unwrap()
+            .into_nodes();
+        let a = nodes[0].try_read().unwrap();
+        let b = nodes[1].try_read().unwrap();
+        assert_eq!((a.value, b.value), (1, 2));
+        assert!(nodes[1].is_aliased_by(&a.edges[0]));
+        assert!(nodes[0].is_aliased_by(&b.edges[0]));
+        assert!(nodes[1].is_aliased_by(&b.edges[1]));
+        assert_eq!(b.edges[0].try_read().unwrap().value, 1);
+    }
+
+    #[test]
+    fn ref_table_rejects_edges_out_of_the_table()
+    {
+        let outside = node(0);
+        let inside = node(1);
+        inside.try_write().unwrap().edges.push(outside.alias());
+        assert!(serde_json::to_string(&RefTable::new(vec![inside])).is_err());
+
+        let dangling = r#"{"len":1,"nodes":[{"value":1,"edges":[1]}]}"#;
+        assert!(serde_json::from_str::<RefTable<Node>>(dangling).is_err());
+        let short = r#"{"len":2,"nodes":[{"value":1,"edges":[]}]}"#;
+        assert!(serde_json::from_str::<RefTable<Node>>(short).is_err());
+    }
+
+    #[test]
+    fn strong_serializes_its_referent()
+    {
+        let strong = Strong::new(vec![1, 2]);
+        assert_eq!(serde_json::to_string(&strong).unwrap(), "[1,2]");
+        let copy: Strong<Vec<i32>> = serde_json::from_str("[1,2]").unwrap();
+        assert_ne!(copy.id(), strong.id());
+        assert_eq!(*copy.try_read().unwrap(), [1, 2]);
+        let weak = strong.alias();
+        let writing = weak.try_write().unwrap();
+        assert!(serde_json::to_string(&strong).is_err());
+        drop(writing);
+    }
+
+    #[test]
+    fn contexts_intern_ids_and_resolve_weak_references()
+    {
+        let (a, b) = (node(1), node(2));
+        let mut ser = SerializeContext::new();
+        assert_eq!(ser.insert(&b), 0);
+        assert_eq!(ser.insert(&a), 1);
+        assert_eq!(ser.insert(&b), 0);
+        let edges = vec![a.alias(), b.alias(), a.alias()];
+        assert!(serde_json::to_string(&edges).is_err());
+        let json = ser.scope(|| serde_json::to_string(&edges)).unwrap();
+        assert_eq!(json, "[1,0,1]");
+
+        let mut de = DeserializeContext::<Node>::new(2);
+        let weaks: Vec<Weak<Node>> = de.scope(|_| serde_json::from_str(&json)).unwrap();
+        assert!(weaks[0].try_read().is_none());
+        let mut de = de.finish().err().unwrap();
+        de.push(Node {
+            value: 2,
+            edges: Vec::new(),
+        });
+        de.push(Node {
+            value: 1,
+            edges: Vec::new(),
+        });
+        let strongs = de.finish().ok().unwrap();
+        assert_eq!(weaks[0].try_read().unwrap().value, 1);
+        assert!(strongs[0].is_aliased_by(&weaks[1]));
+        assert!(serde_json::from_str::<Vec<Weak<Node>>>("[0]").is_err());
+    }
+
+    #[test]
+    fn deserialize_context_drops_only_filled_values()
+    {
+        struct Dropped(Rc<Cell<usize>>);
+
+        impl Drop for Dropped
+        {
+            fn drop(&mut self) { self.0.set(self.0.get() + 1) }
+        }
+
+        let drops = Rc::new(Cell::new(0));
+        let mut de = DeserializeContext::<Dropped>::new(2);
+        de.push(Dropped(drops.clone()));
+        drop(de);
+        assert_eq!(drops.get(), 1);
+    }
+
+    #[test]
+    fn ref_table_lengths_do_not_allocate_up_front()
+    {
+        let huge = format!(r#"{{"len":{},"nodes":[]}}"#, usize::MAX);
+        let err = serde_json::from_str::<RefTable<Node>>(&huge).err().unwrap();
+        assert!(err.to_string().starts_with("invalid length 0"));
+        let forward = format!(
+            r#"{{"len":{},"nodes":[{{"value":1,"edges":[{}]}}]}}"#,
+            usize::MAX,
+            usize::MAX - 1
+        );
+        let err = serde_json::from_str::<RefTable<Node>>(&forward)
+            .err()
+            .unwrap();
+        assert!(err.to_string().starts_with("invalid length 1"));
+    }
+}
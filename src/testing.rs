@@ -0,0 +1,87 @@
+use std::{
+    cell::RefCell,
+    collections::HashSet,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use lazy_static::lazy_static;
+
+thread_local! {
+    // ids allocated on this thread inside the innermost running leak check
+    static CHECKED : RefCell<Option<Vec<u64>>> = const { RefCell::new(None) };
+}
+
+lazy_static! {
+    // checked allocations not freed yet, whichever thread frees them
+    static ref LIVE: parking_lot::Mutex<HashSet<u64>> = parking_lot::Mutex::new(HashSet::new());
+}
+
+// size of LIVE, so that frees can skip its lock when nothing is checked
+static TRACKED: AtomicUsize = AtomicUsize::new(0);
+
+pub(crate) fn allocated(id: u64)
+{
+    CHECKED.with_borrow_mut(|checked| {
+        if let Some(ids) = checked {
+            ids.push(id);
+            LIVE.lock().insert(id);
+            TRACKED.fetch_add(1, Ordering::SeqCst);
+        }
+    })
+}
+
+pub(crate) fn freed(id: u64)
+{
+    if TRACKED.load(Ordering::SeqCst) != 0 && LIVE.lock().remove(&id) {
+        TRACKED.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Runs `body` and panics if a strong reference it allocated on this thread
+/// is still live afterwards, e.g. one that was forgotten, or one whose drop is
+/// still deferred because a guard on it was forgotten. Frees on other threads
+/// count, so references sent elsewhere and dropped there do not.
+pub fn leak_check<F, R>(body: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    let checking = Checking(CHECKED.replace(Some(Vec::new())));
+    let res = body();
+    let leaked = checking.finish();
+    if leaked != 0 {
+        panic!(
+            "leak check failed: {leaked} strong reference(s) still live or awaiting deferred drop"
+        )
+    }
+    res
+}
+
+// the enclosing check, put back even if the body unwinds
+struct Checking(Option<Vec<u64>>);
+
+impl Checking
+{
+    fn finish(mut self) -> usize
+    {
+        let leaked = self.restore();
+        std::mem::forget(self);
+        leaked
+    }
+
+    // stops tracking this check's ids, returning how many were still live
+    fn restore(&mut self) -> usize
+    {
+        let ids = CHECKED.replace(self.0.take()).unwrap_or_default();
+        let leaked = {
+            let mut live = LIVE.lock();
+            ids.into_iter().filter(|id| live.remove(id)).count()
+        };
+        TRACKED.fetch_sub(leaked, Ordering::SeqCst);
+        leaked
+    }
+}
+
+impl Drop for Checking
+{
+    fn drop(&mut self) { self.restore(); }
+}
@@ -0,0 +1,1768 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering::SeqCst},
+        Arc,
+    },
+    thread,
+};
+
+use crate::{tracking::Tracking, *};
+
+fn counter() -> (Arc<AtomicUsize>, impl Fn() -> usize)
+{
+    let count = Arc::new(AtomicUsize::new(0));
+    let read = count.clone();
+    (count, move || read.load(SeqCst))
+}
+
+#[test]
+fn frozen_reads_skip_the_lock()
+{
+    let frozen = FrozenStrong::new(5);
+    let reads: Vec<&i32> = (0..3).map(|_| frozen.get()).collect();
+    // upgrading only succeeds if the freeze holds the sole shared lock
+    let account = frozen.0.account();
+    assert!(account.try_upgrade());
+    unsafe { account.downgrade() };
+    assert_eq!(reads, [&5, &5, &5]);
+}
+
+#[test]
+fn frozen_aliases_are_read_only()
+{
+    let strong = Strong::new(5);
+    let weak = strong.alias();
+    let frozen = strong.freeze();
+    let alias = frozen.alias();
+    assert_eq!(*alias.try_read().unwrap(), 5);
+    assert!(weak.try_write().is_none());
+    assert!(weak.write().is_none());
+    drop(frozen);
+    assert!(!alias.is_valid());
+    assert!(alias.try_read().is_none());
+}
+
+#[test]
+fn try_split_moves_halves_into_new_allocations()
+{
+    let pair = Strong::new((String::from("a"), 3u32));
+    let weak = pair.alias();
+    let (a, b) = pair.try_split(|pair| pair).ok().unwrap();
+    assert!(!weak.is_valid());
+    assert_eq!((a.read().as_str(), *b.read()), ("a", 3));
+
+    let pair = Strong::new((1, 2));
+    let weak = pair.alias();
+    let reading = weak.try_read().unwrap();
+    let pair = pair.try_split(|pair| pair).err().unwrap();
+    drop(reading);
+    assert!(pair.try_split(|pair| pair).is_ok());
+}
+
+#[test]
+fn on_invalidate_fires_once_on_the_freeing_thread()
+{
+    let strong = Strong::new(1);
+    let weak = strong.alias();
+    let (count, fired) = counter();
+    let hook = count.clone();
+    weak.on_invalidate(move || _ = hook.fetch_add(1, SeqCst));
+    let sendable = strong.send();
+    thread::spawn(move || drop(sendable.into_inner()))
+        .join()
+        .unwrap();
+    assert_eq!(fired(), 1);
+    weak.on_invalidate(move || _ = count.fetch_add(1, SeqCst));
+    assert_eq!(fired(), 2);
+}
+
+#[test]
+fn gen_lazy_initializes_once_on_first_access()
+{
+    let (count, calls) = counter();
+    let lazy = GenLazy::new(move || count.fetch_add(1, SeqCst) + 10);
+    assert_eq!(calls(), 0);
+    let weak = lazy.alias();
+    assert_eq!(*lazy.get().unwrap(), 10);
+    assert_eq!(*weak.try_read().unwrap(), 10);
+    assert_eq!(calls(), 1);
+    drop(lazy);
+    assert!(!weak.is_valid());
+}
+
+#[test]
+fn read_while_holds_one_lock_for_the_scan()
+{
+    let strong = Strong::new(vec![1, 2, 3, -1, 4]);
+    let weak = strong.alias();
+    let taken = strong.read_while(|x| {
+        assert!(weak.try_write().is_none());
+        *x > 0
+    });
+    assert_eq!(taken, Some(3));
+    let writing = weak.try_write().unwrap();
+    assert_eq!(strong.read_while(|_| true), None);
+    drop(writing);
+}
+
+#[test]
+fn guards_compare_by_referent()
+{
+    let a = Strong::new(5);
+    let b = Strong::new(5);
+    let (wa, wb) = (a.alias(), b.alias());
+    let reading = wa.try_read().unwrap();
+    assert!(reading == 5);
+    assert!(reading == wb.try_read().unwrap());
+    drop(reading);
+    let writing = wa.try_write().unwrap();
+    assert!(writing == 5);
+    assert!(writing == wb.try_write().unwrap());
+}
+
+struct Dropped(Arc<AtomicUsize>);
+
+impl Drop for Dropped
+{
+    fn drop(&mut self) { self.0.fetch_add(1, SeqCst); }
+}
+
+#[test]
+fn drop_is_deferred_until_guards_are_released()
+{
+    let (count, drops) = counter();
+    let strong = Strong::new(Dropped(count));
+    let weak = strong.alias();
+    let reading = weak.try_read().unwrap();
+    drop(strong);
+    assert_eq!(drops(), 0);
+    drop(reading);
+    assert_eq!(drops(), 1);
+    assert!(!weak.is_valid());
+
+    let strong = Strong::new(7);
+    let weak = strong.alias();
+    let reading = weak.try_read().unwrap();
+    let inner = strong.into_inner_deferred();
+    assert!(inner.try_get().is_none());
+    drop(reading);
+    assert_eq!(*inner.try_get().unwrap(), 7);
+}
+
+#[test]
+fn deferred_global_drop_runs_on_the_thread_releasing_the_guard()
+{
+    let (count, drops) = counter();
+    let strong = Strong::new(Dropped(count));
+    let shared = strong.alias().share();
+    let (locked_tx, locked_rx) = std::sync::mpsc::channel();
+    let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+    let reader = thread::spawn(move || {
+        let reading = shared.try_read().unwrap();
+        locked_tx.send(()).unwrap();
+        release_rx.recv().unwrap();
+        drop(reading);
+    });
+    locked_rx.recv().unwrap();
+    let sendable = strong.send();
+    // the dropping thread exits before the guard is released
+    thread::spawn(move || drop(sendable.into_inner()))
+        .join()
+        .unwrap();
+    assert_eq!(drops(), 0);
+    release_tx.send(()).unwrap();
+    reader.join().unwrap();
+    assert_eq!(drops(), 1);
+}
+
+#[test]
+fn with_escalates_to_a_write_only_when_asked()
+{
+    let strong = Strong::new(5);
+    let weak = strong.alias();
+    assert_eq!(weak.with(|x| ReadResult::Done(*x), |_| 0), Some(5));
+    let bumped = weak.with(
+        |_| ReadResult::Escalate,
+        |x| {
+            *x += 1;
+            *x
+        },
+    );
+    assert_eq!(bumped, Some(6));
+    let reading = weak.try_read().unwrap();
+    assert_eq!(weak.with(|_| ReadResult::Escalate, |_| ()), None);
+    drop(reading);
+    drop(strong);
+    assert_eq!(weak.with(|x| ReadResult::Done(*x), |_| 0), None);
+}
+
+#[test]
+fn allocation_ids_are_never_reused()
+{
+    let strong = Strong::new(5);
+    let weak = strong.alias();
+    let id = strong.id();
+    assert_eq!(weak.source_id(), Some(id));
+    drop(strong);
+    assert_eq!(weak.source_id(), None);
+    // likely reuses the account, but never the id
+    let strong = Strong::new(5);
+    assert_ne!(strong.id(), id);
+}
+
+#[test]
+fn strong_and_weak_format_the_referent_address()
+{
+    let strong = Strong::new(5);
+    let weak = strong.alias();
+    assert_eq!(format!("{strong:p}"), format!("{weak:p}"));
+    assert_eq!(format!("{strong:p}"), format!("{:p}", strong.as_ptr()));
+}
+
+#[test]
+fn snapshot_is_independent_of_later_writes()
+{
+    let strong = Strong::new(vec![String::from("a")]);
+    let snapshot = strong.snapshot().unwrap();
+    strong.alias().try_write().unwrap().push("b".into());
+    assert_eq!(snapshot, ["a"]);
+    let weak = strong.alias();
+    let writing = weak.try_write().unwrap();
+    assert_eq!(strong.snapshot(), None);
+    drop(writing);
+}
+
+#[test]
+fn volatile_accessors_round_trip()
+{
+    let strong = Strong::new(5u32);
+    let weak = strong.alias();
+    let mut writing = weak.try_write().unwrap();
+    writing.write_volatile(9);
+    assert_eq!(writing.read_volatile(), 9);
+    drop(writing);
+    assert_eq!(weak.try_read().unwrap().read_volatile(), 9);
+}
+
+#[test]
+fn sweep_reclaims_drops_whose_guards_are_gone()
+{
+    let (count, drops) = counter();
+    let strong = Strong::new(Dropped(count));
+    let weak = strong.alias();
+    std::mem::forget(weak.try_read().unwrap());
+    drop(strong);
+    assert_eq!(sweep(), 0);
+    // release the forgotten guard's lock behind the queue's back
+    unsafe { weak.0.account().unlock_shared() };
+    assert_eq!(drops(), 0);
+    assert_eq!(sweep(), 1);
+    assert_eq!(drops(), 1);
+}
+
+#[test]
+fn write_pair_mut_locks_both_or_neither()
+{
+    let a = Strong::new(5u32);
+    let b = Strong::new(String::new());
+    let (mut x, mut y) = write_pair_mut(&a, &b).unwrap();
+    *x += 1;
+    y.push('a');
+    drop((x, y));
+    assert!(write_pair_mut(&a, &a).is_none());
+    let weak = b.alias();
+    let reading = weak.try_read().unwrap();
+    assert!(write_pair_mut(&a, &b).is_none());
+    assert!(a.alias().try_write().is_some());
+    drop(reading);
+    assert_eq!(*a.read(), 6);
+    assert_eq!(*b.read(), "a");
+}
+
+#[test]
+fn wrap_keeps_the_allocation_and_its_aliases()
+{
+    #[repr(transparent)]
+    struct Meters
+    {
+        value: u64,
+    }
+
+    unsafe impl TransparentWrapper<u64> for Meters {}
+
+    let strong = Strong::new(5u64);
+    let weak = strong.alias();
+    let wrapped: Strong<Meters> = strong.wrap();
+    assert_eq!(wrapped.read().value, 5);
+    assert!(wrapped.is_aliased_by(&weak));
+    drop(wrapped);
+    assert!(!weak.is_valid());
+}
+
+#[test]
+fn read_fast_shares_the_lock_with_ordinary_readers()
+{
+    let strong = Strong::new(5u64);
+    let weak = strong.alias();
+    let fast = strong.read_fast().unwrap();
+    let reading = weak.try_read().unwrap();
+    assert_eq!((*fast, *reading), (5, 5));
+    assert!(weak.try_write().is_none());
+    drop((fast, reading));
+    let writing = weak.try_write().unwrap();
+    assert!(strong.read_fast().is_none());
+    drop(writing);
+}
+
+#[test]
+fn validated_batch_locks_all_or_nothing()
+{
+    let strongs: Vec<_> = (0..3).map(Strong::new).collect();
+    let weaks: Vec<_> = strongs.iter().map(Strong::alias).collect();
+    let batch = ValidatedBatch::new(&weaks).unwrap();
+    assert_eq!(batch.len(), 3);
+    assert_eq!(batch.get(2), Some(&2));
+    assert!(weaks[1].try_write().is_none());
+    drop(batch);
+
+    let writing = weaks[1].try_write().unwrap();
+    assert!(ValidatedBatch::new(&weaks).is_none());
+    assert!(weaks[0].try_write().is_some());
+    drop(writing);
+}
+
+#[test]
+fn on_drop_runs_when_the_allocation_is_freed()
+{
+    let (count, events) = counter();
+    let mut strong = Strong::new(Dropped(count.clone()));
+    let weak = strong.alias();
+    let hook = count.clone();
+    strong.on_drop(move || _ = hook.fetch_add(10, SeqCst));
+    let reading = weak.try_read().unwrap();
+    drop(strong);
+    assert_eq!(events(), 0);
+    drop(reading);
+    assert_eq!(events(), 11);
+
+    let mut strong = Strong::new(0);
+    strong.on_drop(move || _ = count.fetch_add(100, SeqCst));
+    let sendable = strong.send();
+    thread::spawn(move || drop(sendable.into_inner()))
+        .join()
+        .unwrap();
+    assert_eq!(events(), 111);
+}
+
+#[test]
+fn transaction_guard_commits_or_rolls_back()
+{
+    let mut strong = Strong::new(vec![1]);
+    let mut guard = strong.begin_write().unwrap();
+    guard.push(2);
+    guard.commit();
+    assert_eq!(*strong.read(), [1, 2]);
+
+    let mut guard = strong.begin_write().unwrap();
+    guard.push(3);
+    guard.rollback();
+    assert_eq!(*strong.read(), [1, 2]);
+
+    let mut guard = strong.begin_write().unwrap();
+    guard.clear();
+    drop(guard);
+    assert!(strong.read().is_empty());
+
+    let weak = strong.alias();
+    let reading = weak.try_read().unwrap();
+    assert!(strong.begin_write().is_none());
+    drop(reading);
+}
+
+#[test]
+fn into_box_invalidates_aliases()
+{
+    let strong = Strong::new(String::from("boxed"));
+    let weak = strong.alias();
+    assert_eq!(*strong.into_box(), "boxed");
+    assert!(!weak.is_valid());
+}
+
+#[test]
+#[should_panic]
+fn into_box_panics_on_a_borrowed_local_referent()
+{
+    let strong = Strong::new(5);
+    let weak = strong.alias();
+    let _reading = weak.try_read().unwrap();
+    strong.into_box();
+}
+
+#[test]
+fn into_box_waits_for_guards_on_global_referents()
+{
+    let strong = Strong::new(5);
+    let shared = strong.alias().share();
+    let reading = shared.try_read().unwrap().into_owned();
+    let sendable = strong.send();
+    let owner = thread::spawn(move || *sendable.into_inner().into_box());
+    while waiter_stats().writers_waiting == 0 {
+        thread::yield_now()
+    }
+    drop(reading);
+    assert_eq!(owner.join().unwrap(), 5);
+    assert!(!shared.is_valid());
+}
+
+#[test]
+fn clone_value_into_keeps_the_destination_aliases()
+{
+    let source = Strong::new(vec![1, 2]);
+    let dest = Strong::new(Vec::new());
+    let weak = dest.alias();
+    assert!(source.clone_value_into(&dest));
+    assert_eq!(*weak.try_read().unwrap(), [1, 2]);
+    assert!(source.clone_value_into(&source));
+
+    let writing = weak.try_write().unwrap();
+    assert!(!source.clone_value_into(&dest));
+    drop(writing);
+}
+
+#[test]
+fn checksum_tracks_buffer_contents()
+{
+    let strong = Strong::new(b"payload".to_vec());
+    let sum = strong.checksum().unwrap();
+    assert_eq!(strong.verify_checksum(sum), Some(true));
+    strong.alias().try_write().unwrap().push(b'!');
+    assert_eq!(strong.verify_checksum(sum), Some(false));
+    let weak = strong.alias();
+    let writing = weak.try_write().unwrap();
+    assert_eq!(strong.checksum(), None);
+    drop(writing);
+}
+
+#[test]
+fn apply_runs_edits_under_one_lock()
+{
+    let strong = Strong::new(vec![1]);
+    let weak = strong.alias();
+    assert!(strong.apply(|vec| {
+        assert!(weak.try_read().is_none());
+        vec.push(2);
+        vec.retain(|x| x % 2 == 0);
+    }));
+    assert_eq!(*strong.read(), [2]);
+    let reading = weak.try_read().unwrap();
+    assert!(!strong.apply(|_| unreachable!()));
+    drop(reading);
+}
+
+#[test]
+fn alias_pinned_guard_keeps_the_projection_valid()
+{
+    let strong = Strong::new((1, String::from("field")));
+    let (reading, field) = strong.alias_pinned(|pair| &pair.1).unwrap();
+    let reading = reading.into_owned();
+    assert!(field.try_write().is_none());
+    drop(strong);
+    assert!(field.is_valid());
+    assert_eq!(*field.try_read().unwrap(), "field");
+    drop(reading);
+    assert!(!field.is_valid());
+}
+
+#[test]
+fn counter_efficiency_accounts_for_every_counter()
+{
+    let before = counter_efficiency();
+    let strongs: Vec<_> = (0..4).map(Strong::new).collect();
+    let during = counter_efficiency();
+    assert_eq!(during.live, before.live + 4);
+    drop(strongs);
+    let after = counter_efficiency();
+    assert_eq!(after.live, before.live);
+    assert_eq!(
+        after.allocated,
+        after.free_listed + after.live + after.retired
+    );
+}
+
+#[test]
+fn string_writers_accept_formatting()
+{
+    use std::fmt::Write;
+
+    let strong = Strong::new(String::from("x"));
+    let weak = strong.alias();
+    write!(weak.try_write().unwrap(), "={}", 1).unwrap();
+    assert_eq!(*strong.read(), "x=1");
+}
+
+#[test]
+fn aliaser_mints_aliases_of_the_captured_generation()
+{
+    let strong = Strong::new(5);
+    let aliaser = strong.aliaser();
+    let copy = aliaser;
+    assert!(copy.mint().ptr_eq(&strong.alias()));
+    drop(strong);
+    let late = aliaser.mint();
+    assert!(!late.is_valid());
+    assert!(late.try_read().is_none());
+}
+
+#[test]
+fn deadlines_are_tried_once_on_local_accounts()
+{
+    let strong = Strong::new(5);
+    let weak = strong.alias();
+    let writing = weak.try_write().unwrap();
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(60);
+    assert!(strong.try_read_until(deadline).is_none());
+    assert!(weak.try_write_until(deadline).is_none());
+    drop(writing);
+    assert!(strong.try_write_until(deadline).is_some());
+}
+
+#[test]
+fn deadlines_wait_on_global_accounts()
+{
+    use std::time::{Duration, Instant};
+
+    let strong = Strong::new(5).send().into_inner();
+    let shared = strong.alias().share();
+    let writing = strong.write().into_owned();
+    let expired = Instant::now() + Duration::from_millis(10);
+    let waiter = shared.clone();
+    let timed_out = thread::spawn(move || waiter.try_read_until(expired).is_none());
+    assert!(timed_out.join().unwrap());
+
+    let waiter = shared.clone();
+    let later = Instant::now() + Duration::from_secs(60);
+    let reader = thread::spawn(move || *waiter.try_read_until(later).unwrap());
+    while waiter_stats().readers_waiting == 0 {
+        thread::yield_now()
+    }
+    drop(writing);
+    assert_eq!(reader.join().unwrap(), 5);
+}
+
+#[test]
+fn alias_index_survives_reallocation()
+{
+    let strong = Strong::new(Vec::with_capacity(1));
+    strong.apply(|vec| vec.push(1));
+    let elem = unsafe { strong.alias_index(0) }.unwrap();
+    assert_eq!(*elem.try_read().unwrap(), 1);
+    assert!(unsafe { strong.alias_index(1) }.is_none());
+    let before = strong.read().as_ptr();
+    strong.apply(|vec| vec.extend(2..100));
+    // the alias still validates and points at the old buffer, wherever the
+    // push moved the elements, which is why alias_index is unsafe; it must
+    // not be dereferenced here
+    assert!(elem.is_valid());
+    assert_eq!(elem.as_ptr(), before);
+}
+
+#[test]
+fn leak_check_passes_clean_bodies()
+{
+    testing::leak_check(|| {
+        Strong::new(1).send().into_inner();
+        let sendable = Strong::new(2).send();
+        thread::spawn(move || drop(sendable.into_inner()))
+            .join()
+            .unwrap();
+        let strong = Strong::new(3);
+        let weak = strong.alias();
+        let reading = weak.try_read().unwrap();
+        drop(strong);
+        drop(reading);
+    });
+}
+
+#[test]
+#[should_panic(expected = "leak check failed: 1 strong reference(s)")]
+fn leak_check_fails_forgotten_strongs()
+{
+    testing::leak_check(|| std::mem::forget(Strong::new(1)));
+}
+
+#[test]
+#[should_panic(expected = "leak check failed: 1 strong reference(s)")]
+fn leak_check_fails_drops_deferred_by_forgotten_guards()
+{
+    testing::leak_check(|| {
+        let strong = Strong::new(1);
+        std::mem::forget(strong.alias().try_read().unwrap().into_owned());
+    });
+}
+
+#[test]
+fn leak_check_recovers_from_panicking_bodies()
+{
+    // the inner check's leak goes with its panic instead of being blamed on
+    // the outer check it was nested in
+    testing::leak_check(|| {
+        let res = std::panic::catch_unwind(|| {
+            testing::leak_check(|| {
+                std::mem::forget(Strong::new(1));
+                panic!("body failed")
+            })
+        });
+        assert!(res.is_err());
+        drop(Strong::new(2));
+    });
+}
+
+#[test]
+fn compare_and_swap_only_replaces_the_expected_value()
+{
+    let strong = Strong::new(1);
+    let weak = strong.alias();
+    assert_eq!(strong.compare_and_swap(&1, 2), Ok(()));
+    assert_eq!(strong.compare_and_swap(&1, 3), Err(3));
+    assert_eq!(*weak.try_read().unwrap(), 2);
+    let reading = weak.try_read().unwrap();
+    assert_eq!(strong.compare_and_swap(&2, 4), Err(4));
+    drop(reading);
+}
+
+#[test]
+fn frozen_references_are_read_concurrently_across_threads()
+{
+    let strong = Strong::new(vec![1, 2, 3]);
+    let weak = strong.alias();
+    let frozen = strong.freeze();
+    let alias = frozen.alias();
+    thread::scope(|scope| {
+        for _ in 0..4 {
+            scope.spawn(|| {
+                for _ in 0..100 {
+                    assert_eq!(frozen.get().iter().sum::<i32>(), 6);
+                    assert_eq!(alias.try_read().unwrap().len(), 3);
+                }
+            });
+        }
+    });
+    assert!(weak.try_write().is_none());
+    assert!(weak.write().is_none());
+    let dropped = thread::spawn(move || drop(frozen));
+    dropped.join().unwrap();
+    assert!(!alias.is_valid());
+}
+
+#[test]
+#[should_panic(expected = "cannot globalize a borrowed")]
+fn freezing_a_borrowed_referent_panics()
+{
+    let strong = Strong::new(1);
+    let weak = strong.alias();
+    let _reading = weak.try_read().unwrap();
+    strong.freeze();
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn json_helpers_round_trip_and_reject_bad_payloads()
+{
+    let strong = Strong::new(vec![1, 2]);
+    assert_eq!(strong.to_json().unwrap(), "[1,2]");
+    strong.update_from_json("[3]").unwrap();
+    assert_eq!(*strong.read(), [3]);
+    assert!(matches!(
+        strong.update_from_json("[oops"),
+        Err(JsonError::Json(_))
+    ));
+    let weak = strong.alias();
+    let reading = weak.try_read().unwrap();
+    assert!(matches!(
+        strong.update_from_json("[4]"),
+        Err(JsonError::Locked)
+    ));
+    drop(reading);
+    assert_eq!(*strong.read(), [3]);
+}
+
+#[test]
+fn published_buffers_are_read_from_any_thread()
+{
+    let strong = Strong::new(vec![1, 2, 3]);
+    let weak = strong.alias();
+    let published = strong.publish();
+    let copy = published;
+    let sum = thread::spawn(move || copy.iter().sum::<i32>());
+    assert_eq!(sum.join().unwrap(), 6);
+    assert_eq!(*weak.try_read().unwrap(), [1, 2, 3]);
+    assert!(weak.try_write().is_none());
+    assert_eq!(&*published, [1, 2, 3]);
+}
+
+#[test]
+fn try_map_result_passes_projection_errors_through()
+{
+    let strong = Strong::new(vec![1, 2]);
+    let weak = strong.alias();
+    let second = weak
+        .try_map_result(|vec| vec.get(1).ok_or("short"))
+        .unwrap();
+    assert_eq!(*second.unwrap().try_read().unwrap(), 2);
+    assert_eq!(
+        weak.try_map_result(|vec| vec.get(5).ok_or("short")).err(),
+        Some("short")
+    );
+    let writing = weak.try_write().unwrap();
+    let locked = strong.alias().try_map_result(|vec| vec.first().ok_or(()));
+    assert!(matches!(locked, Ok(None)));
+    drop(writing);
+}
+
+#[test]
+fn self_healing_rebuilds_only_stale_referents()
+{
+    let strong = Strong::new(1);
+    let mut healing = SelfHealing::new(strong.alias());
+    let (count, rebuilds) = counter();
+    let rebuild = || {
+        count.fetch_add(1, SeqCst);
+        Strong::new(2)
+    };
+    assert_eq!(*healing.get_or_rebuild(rebuild).unwrap(), 1);
+    let weak = strong.alias();
+    let writing = weak.try_write().unwrap();
+    assert!(healing.get_or_rebuild(rebuild).is_none());
+    drop(writing);
+    drop(strong);
+    assert_eq!(*healing.get_or_rebuild(rebuild).unwrap(), 2);
+    assert_eq!(*healing.get_or_rebuild(rebuild).unwrap(), 2);
+    assert_eq!(rebuilds(), 1);
+}
+
+#[test]
+fn get_disjoint_mut_hands_out_distinct_elements()
+{
+    let strong = Strong::new(vec![1, 2, 3, 4]);
+    {
+        let mut disjoint = strong.get_disjoint_mut(&[3, 0]).unwrap();
+        assert_eq!(disjoint.len(), 2);
+        assert_eq!(disjoint[0], 4);
+        disjoint[1] = 10;
+        for elem in disjoint.iter_mut() {
+            *elem *= 2;
+        }
+    }
+    assert_eq!(*strong.try_read().unwrap(), vec![20, 2, 3, 8]);
+}
+
+#[test]
+fn get_disjoint_mut_rejects_bad_index_sets()
+{
+    let strong = Strong::new(vec![1, 2, 3]);
+    assert!(strong.get_disjoint_mut(&[0, 0]).is_none());
+    assert!(strong.get_disjoint_mut(&[3]).is_none());
+    assert!(strong.get_disjoint_mut(&[]).unwrap().is_empty());
+    let reading = strong.try_read().unwrap();
+    assert!(strong.get_disjoint_mut(&[0]).is_none());
+    drop(reading);
+    assert!(strong.get_disjoint_mut(&[0]).is_some());
+}
+
+#[test]
+fn acquire_publication_sees_writes_made_before_the_publish()
+{
+    const ROUNDS: usize = 10_000;
+    let strong = Strong::new([(); 8].map(|_| AtomicUsize::new(0)))
+        .send()
+        .into_inner();
+    let weak = strong.alias().share();
+    let base = strong.alias().acquire_publication().unwrap() as usize;
+    let consumer = thread::spawn(move || {
+        let slots = weak.read().unwrap();
+        let mut seen = 0;
+        while seen < ROUNDS {
+            seen = weak.acquire_publication().unwrap() as usize - base;
+            for slot in slots.iter() {
+                assert!(
+                    slot.load(std::sync::atomic::Ordering::Relaxed) >= seen,
+                    "stale read"
+                );
+            }
+        }
+    });
+    // both sides only hold shared locks, so the publication count is the
+    // sole thing ordering the producer's writes before the consumer's reads
+    let slots = strong.try_read().unwrap();
+    for round in 1..=ROUNDS {
+        for slot in slots.iter() {
+            slot.store(round, std::sync::atomic::Ordering::Relaxed);
+        }
+        assert_eq!(strong.publish_fence() as usize - base, round);
+    }
+    drop(slots);
+    consumer.join().unwrap();
+    let weak = strong.alias();
+    drop(strong);
+    assert!(!weak.acquire_validity());
+}
+
+#[test]
+fn split_owned_hands_the_pair_back_while_locked()
+{
+    let pair = Strong::new((1, String::from("two")));
+    let weak = pair.alias();
+    let reading = weak.try_read().unwrap();
+    let pair = pair.split_owned().err().unwrap();
+    drop(reading);
+    let (a, b) = pair.split_owned().ok().unwrap();
+    assert!(!weak.is_valid());
+    assert_eq!(*a.try_read().unwrap(), 1);
+    let b = b.send();
+    let len = thread::spawn(move || b.into_inner().try_read().map(|b| b.len()))
+        .join()
+        .unwrap();
+    assert_eq!(len, Some(3));
+}
+
+#[test]
+fn transaction_locks_several_references_until_the_body_returns()
+{
+    let from = Strong::new(10);
+    let to = Strong::new(0);
+    let moved = transaction(|tx| {
+        let from = tx.write(&from)?;
+        let to = tx.write(&to)?;
+        *to += *from;
+        *from = 0;
+        Some(*to)
+    });
+    assert_eq!(moved, Some(10));
+    assert_eq!(*from.try_read().unwrap(), 0);
+    assert!(to.try_write().is_some());
+}
+
+#[test]
+fn transaction_aborts_on_contention_and_releases_its_locks()
+{
+    let a = Strong::new(1);
+    let b = Strong::new(2);
+    let weak = b.alias();
+    let reading = weak.try_read().unwrap();
+    assert_eq!(transaction(|tx| Some(*tx.read(&a)? + *tx.write(&b)?)), None);
+    assert!(a.try_write().is_some());
+    assert_eq!(
+        transaction(|tx| Some(*tx.read(&a)? + *tx.read(&b)?)),
+        Some(3)
+    );
+    drop(reading);
+    assert_eq!(
+        transaction(|tx| tx.write(&a).and(tx.write(&a)).copied()),
+        None
+    );
+    assert!(a.try_write().is_some());
+}
+
+#[test]
+fn waiter_stats_counts_threads_blocked_on_a_global_lock()
+{
+    let strong = Strong::new(0).send().into_inner();
+    let weak = strong.alias().share();
+    let reading = strong.try_read().unwrap();
+    let writer = thread::spawn(move || *weak.write().unwrap() = 1);
+    while waiter_stats().writers_waiting == 0 {
+        thread::yield_now();
+    }
+    drop(reading);
+    writer.join().unwrap();
+    assert_eq!(*strong.try_read().unwrap(), 1);
+}
+
+#[test]
+fn keepalive_blocks_writers_while_it_lives()
+{
+    let strong = Strong::new(1);
+    let (keepalive, weak) = strong.alias_with_keepalive().unwrap();
+    assert!(strong.try_write().is_none());
+    assert!(weak.try_write().is_none());
+    assert_eq!(*weak.try_read().unwrap(), 1);
+    drop(keepalive);
+    assert!(weak.try_write().is_some());
+    let writing = strong.try_write().unwrap();
+    assert!(strong.alias_with_keepalive().is_none());
+    drop(writing);
+}
+
+#[test]
+fn keepalive_outlives_the_owner_until_released()
+{
+    let (count, drops) = counter();
+    let strong = Strong::new(Dropped(count));
+    let (keepalive, weak) = strong.alias_with_keepalive().unwrap();
+    drop(strong);
+    assert_eq!(drops(), 0);
+    assert!(weak.is_valid());
+    assert!(weak.try_read().is_some());
+    drop(keepalive);
+    assert_eq!(drops(), 1);
+    assert!(!weak.is_valid());
+}
+
+#[test]
+fn keepalive_defers_the_drop_of_a_global_owner()
+{
+    let (count, drops) = counter();
+    let strong = Strong::new(Dropped(count)).send().into_inner();
+    let (keepalive, weak) = strong.alias_with_keepalive().unwrap();
+    drop(strong);
+    assert_eq!(drops(), 0);
+    assert!(weak.is_valid());
+    drop(keepalive);
+    assert_eq!(drops(), 1);
+    assert!(!weak.is_valid());
+}
+
+#[test]
+fn isolation_ends_with_the_last_pool_and_its_escaped_accounts()
+{
+    assert!(!local_ledger::isolated());
+    with_isolated_pool(|| {
+        with_isolated_pool(|| drop(Strong::new(0)));
+        assert!(local_ledger::isolated());
+    });
+    assert!(!local_ledger::isolated());
+    let escaped = with_isolated_pool(|| Strong::new(1));
+    assert!(local_ledger::isolated());
+    let before = counter_efficiency();
+    drop(escaped);
+    assert_eq!(counter_efficiency(), before);
+    assert!(!local_ledger::isolated());
+}
+
+#[test]
+fn read_map_releases_the_lock_before_returning()
+{
+    let strong = Strong::new(String::from("genref"));
+    assert_eq!(strong.read_map(String::len), Some(6));
+    assert!(strong.try_write().is_some());
+    let weak = strong.alias();
+    let writing = weak.try_write().unwrap();
+    assert_eq!(strong.read_map(String::len), None);
+    drop(writing);
+}
+
+#[test]
+fn rebase_follows_the_referent_to_its_new_owner()
+{
+    let old = Strong::new(vec![1, 2]);
+    let mut weak = old.alias();
+    let new = Strong::new(old.try_take().ok().unwrap().to_vec());
+    assert!(!weak.is_valid());
+    weak.rebase(&new);
+    assert!(weak.is_valid());
+    assert!(new.is_aliased_by(&weak));
+    assert_eq!(*weak.try_read().unwrap(), vec![1, 2]);
+}
+
+#[test]
+fn fold_aggregates_under_one_read_lock()
+{
+    let strong = Strong::new(vec![1, 2, 3, 4]);
+    assert_eq!(strong.fold(0, |sum, x| sum + x), Some(10));
+    assert_eq!(
+        strong
+            .fold(String::new(), |s, x| s + &x.to_string())
+            .as_deref(),
+        Some("1234")
+    );
+    let weak = strong.alias();
+    let writing = weak.try_write().unwrap();
+    assert_eq!(strong.fold(0, |sum, x| sum + x), None);
+    drop(writing);
+}
+
+#[test]
+fn as_cell_shares_get_and_set_while_write_locked()
+{
+    let strong = Strong::new(1);
+    {
+        let cell = strong.as_cell().unwrap();
+        let (left, right) = (&*cell, &*cell);
+        left.set(right.get() + 1);
+        assert_eq!(right.get(), 2);
+        assert!(strong.try_read().is_none());
+    }
+    assert_eq!(*strong.try_read().unwrap(), 2);
+    let global = Strong::new(1).send().into_inner();
+    assert!(global.as_cell().is_none());
+}
+
+#[test]
+fn new_allocates_a_fresh_unaliased_referent()
+{
+    let (count, drops) = counter();
+    let a = Strong::new(Dropped(count.clone()));
+    let b = Strong::new(Dropped(count));
+    assert_ne!(a.id(), b.id());
+    assert!(!a.is_aliased_by(&b.alias()));
+    assert!(a.try_read().is_some());
+    drop(a);
+    assert_eq!(drops(), 1);
+    drop(b);
+    assert_eq!(drops(), 2);
+}
+
+#[test]
+fn blocking_locks_wait_for_other_threads()
+{
+    let strong = Strong::new(0).send().into_inner();
+    let weak = strong.alias().share();
+    let writing = strong.write();
+    let reader = thread::spawn(move || *weak.read().unwrap());
+    thread::sleep(std::time::Duration::from_millis(20));
+    drop(writing);
+    assert_eq!(reader.join().unwrap(), 0);
+    *strong.write() = 1;
+    assert_eq!(*strong.read(), 1);
+}
+
+#[test]
+fn blocking_weak_locks_fail_on_invalid_referents()
+{
+    let strong = Strong::new(0);
+    let weak = strong.alias();
+    assert_eq!(*weak.read().unwrap(), 0);
+    *weak.write().unwrap() = 1;
+    drop(strong);
+    assert!(weak.read().is_none());
+    assert!(weak.write().is_none());
+}
+
+#[test]
+fn unbounded_timeouts_try_thread_local_locks_once()
+{
+    let strong = Strong::new(0);
+    let weak = strong.alias();
+    let reading = weak.try_read().unwrap();
+    assert!(strong.try_write_for(Duration::MAX).is_none());
+    assert!(weak.try_write_for(Duration::MAX).is_none());
+    assert!(strong.try_read_for(Duration::MAX).is_some());
+    drop(reading);
+    let writing = weak.try_write_for(Duration::MAX).unwrap();
+    assert!(strong.try_read_for(Duration::MAX).is_none());
+    assert!(weak.try_read_for(Duration::MAX).is_none());
+    drop(writing);
+    drop(strong);
+    assert!(weak.try_read_for(Duration::MAX).is_none());
+}
+
+#[test]
+fn unbounded_timeouts_wait_on_global_locks()
+{
+    let strong = Strong::new(0).send().into_inner();
+    let weak = strong.alias().share();
+    let writing = strong.try_write_for(Duration::MAX).unwrap();
+    let reader = thread::spawn(move || weak.try_read_for(Duration::MAX).map(|r| *r));
+    thread::sleep(Duration::from_millis(20));
+    drop(writing);
+    assert_eq!(reader.join().unwrap(), Some(0));
+    assert!(strong.try_read_for(Duration::MAX).is_some());
+}
+
+#[test]
+fn downgrade_keeps_the_referent_locked_against_writers()
+{
+    for strong in [Strong::new(0), Strong::new(0).send().into_inner()] {
+        let weak = strong.alias();
+        let mut writing = weak.try_write().unwrap();
+        *writing = 1;
+        let reading = writing.downgrade();
+        assert_eq!(*reading, 1);
+        assert!(strong.try_write().is_none());
+        assert_eq!(*strong.try_read().unwrap(), 1);
+        drop(reading);
+        assert!(strong.try_write().is_some());
+    }
+}
+
+#[test]
+fn try_upgrade_succeeds_only_for_the_sole_reader()
+{
+    for strong in [Strong::new(0), Strong::new(0).send().into_inner()] {
+        let weak = strong.alias();
+        let reading = weak.try_read().unwrap();
+        let other = strong.try_read().unwrap();
+        let reading = reading.try_upgrade().err().unwrap();
+        drop(other);
+        let mut writing = reading.try_upgrade().ok().unwrap();
+        *writing = 1;
+        assert!(strong.try_read().is_none());
+        drop(writing);
+        assert_eq!(*strong.try_read().unwrap(), 1);
+    }
+}
+
+#[test]
+fn mapped_reading_keeps_the_original_lock()
+{
+    let strong = Strong::new((1, String::from("two")));
+    let weak = strong.alias();
+    let name = weak.try_read().unwrap().map(|pair| pair.1.as_str());
+    assert_eq!(&*name, "two");
+    assert!(strong.try_write().is_none());
+    drop(name);
+    assert!(strong.try_write().is_some());
+}
+
+#[test]
+fn mapped_writing_keeps_the_original_lock()
+{
+    let strong = Strong::new((1, vec![2]));
+    let weak = strong.alias();
+    let mut items = weak.try_write().unwrap().map(|pair| &mut pair.1);
+    items.push(3);
+    assert!(strong.try_read().is_none());
+    drop(items);
+    assert_eq!(*strong.try_read().unwrap(), (1, vec![2, 3]));
+}
+
+#[test]
+fn release_parks_ownership_until_reclaimed()
+{
+    let (count, drops) = counter();
+    let strong = Strong::new(Dropped(count));
+    let weak = strong.alias();
+    strong.release();
+    assert!(weak.is_valid());
+    let strong = weak.try_reclaim().unwrap();
+    assert!(weak.try_reclaim().is_none());
+    drop(strong);
+    assert_eq!(drops(), 1);
+    assert!(!weak.is_valid());
+}
+
+#[test]
+fn projected_aliases_cannot_reclaim()
+{
+    let strong = Strong::from_vec(vec![1u8, 2, 3]);
+    let prefix = strong.alias_range(0..1);
+    let whole = strong.alias();
+    strong.release();
+    assert!(prefix.try_reclaim().is_none());
+    let reading = whole.try_read().unwrap();
+    assert!(whole.try_reclaim().is_none());
+    drop(reading);
+    let strong = whole.try_reclaim().unwrap();
+    assert_eq!(*strong.try_read().unwrap(), [1, 2, 3]);
+}
+
+#[test]
+fn try_unwrap_moves_the_value_out_unless_locked()
+{
+    let strong = Strong::new(String::from("value"));
+    let weak = strong.alias();
+    let reading = weak.try_read().unwrap();
+    let strong = strong.try_unwrap().err().unwrap();
+    drop(reading);
+    assert_eq!(strong.try_unwrap().ok().unwrap(), "value");
+    assert!(!weak.is_valid());
+}
+
+#[test]
+fn raw_parts_round_trip_losslessly()
+{
+    let strong = Strong::new(1);
+    let (id, generation, ptr) = (strong.id(), strong.generation(), strong.as_ptr());
+    let weak = unsafe { Weak::from_raw(strong.alias().into_raw()) };
+    let parts = strong.into_raw();
+    assert!(parts.is_strong());
+    assert_eq!(parts.as_ptr(), ptr);
+    let strong = unsafe { Strong::from_raw(parts) };
+    assert_eq!((strong.id(), strong.generation()), (id, generation));
+    assert!(strong.is_aliased_by(&weak));
+    drop(strong);
+    assert!(!weak.is_valid());
+}
+
+#[test]
+fn raw_parts_keep_slice_metadata()
+{
+    let strong = Strong::from_vec(vec![1, 2, 3]);
+    let parts = strong.alias_range(1..3).into_raw();
+    assert!(!parts.is_strong());
+    let weak = unsafe { Weak::from_raw(parts) };
+    assert_eq!(*weak.try_read().unwrap(), [2, 3]);
+    let strong = unsafe { Strong::from_raw(strong.into_raw()) };
+    assert_eq!(strong.try_read().unwrap().len(), 3);
+}
+
+#[test]
+#[should_panic(expected = "raw parts of a weak reference used as a strong reference")]
+fn weak_raw_parts_cannot_become_strong()
+{
+    let strong = Strong::new(1);
+    let _ = unsafe { Strong::from_raw(strong.alias().into_raw()) };
+}
+
+#[test]
+fn get_mut_requires_an_unaliased_owner()
+{
+    let mut strong = Strong::new(1);
+    *strong.get_mut().unwrap() += 1;
+    let _weak = strong.alias();
+    assert!(strong.get_mut().is_none());
+    assert_eq!(*strong.try_read().unwrap(), 2);
+}
+
+#[test]
+fn as_ptr_points_at_the_referent_or_its_projection()
+{
+    let strong = Strong::new((1u32, 2u32));
+    assert_eq!(strong.as_ptr(), &*strong.try_read().unwrap() as *const _);
+    assert_eq!(strong.alias().as_ptr(), strong.as_ptr());
+    let second = strong.alias_of(|pair| &pair.1);
+    assert_eq!(second.as_ptr(), unsafe { &(*strong.as_ptr()).1 }
+        as *const u32);
+    drop(strong);
+    assert!(!second.as_ptr().is_null());
+}
+
+#[test]
+fn ptr_eq_compares_allocations_not_values()
+{
+    let a = Strong::new([1, 2]);
+    let b = Strong::new([1, 2]);
+    let first = a.alias();
+    assert!(first.ptr_eq(&a.alias()));
+    assert!(!first.ptr_eq(&b.alias()));
+    assert!(a.is_aliased_by(&a.alias_of(|pair| &pair[1])));
+    assert!(!b.is_aliased_by(&first));
+    drop(a);
+    // likely reuses the account of `a`, but under a new generation
+    let c = Strong::new([1, 2]);
+    assert!(!first.ptr_eq(&c.alias()));
+}
+
+#[test]
+fn generations_move_on_when_the_referent_is_dropped()
+{
+    let strong = Strong::new(1);
+    let weak = strong.alias();
+    assert!(weak.is_valid());
+    assert_eq!(weak.generation(), strong.generation());
+    let generation = strong.generation();
+    drop(strong);
+    assert!(!weak.is_valid());
+    assert_eq!(weak.generation(), generation);
+    let global = Strong::new(1).send().into_inner();
+    let weak = global.alias();
+    assert!(weak.is_valid());
+    drop(global);
+    assert!(!weak.is_valid());
+}
+
+#[test]
+fn dangling_weak_references_are_never_valid()
+{
+    const SENTINEL: Weak<u8> = Weak::dangling();
+    let default = Weak::<String>::default();
+    for weak in [SENTINEL, Weak::default()] {
+        assert!(!weak.is_valid());
+        assert!(weak.try_read().is_none());
+        assert!(weak.try_write().is_none());
+        assert!(weak.read().is_none());
+    }
+    assert!(default.try_read().is_none());
+    assert!(!default.ptr_eq(&Strong::new(String::new()).alias()));
+}
+
+#[test]
+fn debug_shows_backend_generation_and_value()
+{
+    let strong = Strong::new(1);
+    let generation = strong.generation();
+    assert_eq!(
+        format!("{strong:?}"),
+        format!("Strong {{ backend: \"local\", generation: {generation}, value: 1 }}")
+    );
+    let weak = strong.alias();
+    let writing = weak.try_write().unwrap();
+    assert!(format!("{weak:?}").ends_with("value: <locked> }"));
+    assert!(format!("{writing:?}").starts_with("Writing {"));
+    drop(writing);
+    let reading = weak.try_read().unwrap();
+    assert!(format!("{reading:?}").ends_with("value: 1 }"));
+    drop(reading);
+    drop(strong);
+    assert!(format!("{weak:?}").ends_with("value: <invalid> }"));
+    let global = Strong::new(2).send().into_inner();
+    assert!(format!("{global:?}").starts_with("Strong { backend: \"global\""));
+    let frozen = Strong::new(3).freeze();
+    assert!(format!("{frozen:?}").ends_with("value: 3 }"));
+    assert!(format!("{:?}", frozen.alias()).ends_with("value: 3 }"));
+    assert!(format!("{:?}", GenRef::from(Strong::new(4))).ends_with("value: 4 }"));
+}
+
+#[test]
+// the hashed allocation id and generation only change once the account is
+// reused, which nothing here does
+#[allow(clippy::mutable_key_type)]
+fn weak_equality_and_hashing_follow_identity()
+{
+    use std::collections::HashSet;
+
+    let a = Strong::new(1);
+    let b = Strong::new(1);
+    assert_eq!(a.alias(), a.alias());
+    assert_ne!(a.alias(), b.alias());
+    let set: HashSet<_> = [a.alias(), a.alias(), b.alias()].into_iter().collect();
+    assert_eq!(set.len(), 2);
+    assert!(set.contains(&b.alias()));
+    let local = b.alias();
+    let shared = b.alias().share().into_inner();
+    assert_eq!(local, shared);
+    assert!(set.contains(&shared));
+    let stale = a.alias();
+    drop(a);
+    assert!(set.contains(&stale));
+    assert_eq!(stale, stale.clone());
+}
+
+#[test]
+fn strong_references_coerce_to_trait_objects()
+{
+    let (count, drops) = counter();
+    let strong: Strong<dyn std::any::Any> = Strong::new(Dropped(count));
+    let weak: Weak<dyn std::any::Any> = strong.alias();
+    assert!(weak.try_read().unwrap().is::<Dropped>());
+    let display: Strong<dyn std::fmt::Display> = Strong::from_box(Box::new(7));
+    assert_eq!(display.try_read().unwrap().to_string(), "7");
+    let narrowed: Weak<dyn std::fmt::Display> = display.alias();
+    drop(display);
+    assert!(!narrowed.is_valid());
+    drop(strong);
+    assert_eq!(drops(), 1);
+    assert!(!weak.is_valid());
+}
+
+#[test]
+fn slice_allocations_share_one_generation()
+{
+    let (count, drops) = counter();
+    let strong = Strong::from_vec(vec![Dropped(count.clone()), Dropped(count)]);
+    assert_eq!(strong.try_read().unwrap().len(), 2);
+    let weak = strong.alias();
+    drop(strong);
+    assert_eq!(drops(), 2);
+    assert!(!weak.is_valid());
+    let boxed = Strong::from_boxed_slice(vec![1, 2, 3].into_boxed_slice());
+    assert_eq!(*boxed.try_read().unwrap(), [1, 2, 3]);
+    let empty = Strong::<[u8]>::from_vec(Vec::new());
+    assert!(empty.try_read().unwrap().is_empty());
+}
+
+#[test]
+fn element_and_range_aliases_share_the_slice_lock()
+{
+    let strong = Strong::from_vec(vec![1, 2, 3, 4]);
+    let second = strong.alias_element(1);
+    let middle = strong.alias_range(1..3);
+    *second.try_write().unwrap() = 20;
+    assert_eq!(*middle.try_read().unwrap(), [20, 3]);
+    let reading = middle.try_read().unwrap();
+    assert!(second.try_write().is_none());
+    drop(reading);
+    assert!(strong.is_aliased_by(&second));
+    assert!(strong.alias_range(4..4).try_read().unwrap().is_empty());
+    drop(strong);
+    assert!(!second.is_valid());
+    assert!(!middle.is_valid());
+}
+
+#[test]
+#[should_panic(expected = "element alias out of bounds")]
+fn element_aliases_are_bounds_checked() { let _ = Strong::from_vec(vec![1]).alias_element(1); }
+
+#[test]
+#[should_panic(expected = "range alias out of bounds")]
+fn range_aliases_are_bounds_checked() { let _ = Strong::from_vec(vec![1]).alias_range(0..2); }
+
+#[test]
+fn pinned_referents_hand_out_pinned_guards()
+{
+    let (count, drops) = counter();
+    let pinned = Strong::pin((1, Dropped(count)));
+    let weak = pinned.alias();
+    {
+        let mut writing = weak.try_write().unwrap();
+        assert_eq!((*writing).0, 1);
+        let mut pin = writing.as_pin_mut().unwrap();
+        unsafe { pin.as_mut().get_unchecked_mut().0 = 2 };
+    }
+    assert_eq!(pinned.try_read().unwrap().as_pin_ref().unwrap().0, 2);
+    assert!(Strong::new(1).try_read().unwrap().as_pin_ref().is_none());
+    drop(pinned);
+    assert_eq!(drops(), 1);
+    assert!(!weak.is_valid());
+}
+
+#[test]
+#[should_panic(expected = "plain mutable access to a pinned referent")]
+fn pinned_referents_refuse_plain_mutable_access()
+{
+    let pinned = Strong::pin(1);
+    let weak = pinned.alias();
+    *weak.try_write().unwrap() = 2;
+}
+
+#[test]
+fn closure_helpers_lock_only_for_the_call()
+{
+    let strong = Strong::new(vec![1]);
+    assert_eq!(strong.with_write(|v| v.push(2)), Some(()));
+    assert_eq!(strong.with_read(Vec::len), Some(2));
+    let weak = strong.alias();
+    assert_eq!(weak.with_write(|v| v.pop()), Some(Some(2)));
+    assert_eq!(weak.with_read(|v| v[0]), Some(1));
+    let reading = weak.try_read().unwrap();
+    assert_eq!(strong.with_read(Vec::len), Some(1));
+    assert_eq!(strong.with_write(|v| v.clear()), None);
+    assert_eq!(weak.with_write(|v| v.clear()), None);
+    drop(reading);
+    drop(strong);
+    assert_eq!(weak.with_read(Vec::len), None);
+    assert_eq!(weak.with_write(|v| v.clear()), None);
+}
+
+#[test]
+fn bulk_locks_take_every_entry_or_none()
+{
+    let strongs: Vec<_> = (0..3).map(Strong::new).collect();
+    let weaks: Vec<_> = strongs.iter().map(Strong::alias).collect();
+    for mut writing in write_all(&weaks, InvalidPolicy::Fail).unwrap() {
+        *writing += 10;
+    }
+    let readings = read_all(&weaks, InvalidPolicy::Fail).unwrap();
+    assert_eq!(
+        readings.iter().map(|r| **r).collect::<Vec<_>>(),
+        [10, 11, 12]
+    );
+    assert!(write_all(&weaks, InvalidPolicy::Skip).is_none());
+    drop(readings);
+    assert!(weaks.iter().all(|weak| weak.try_write().is_some()));
+}
+
+#[test]
+fn bulk_locks_skip_or_fail_on_invalid_entries()
+{
+    let mut strongs: Vec<_> = (0..3).map(Strong::new).collect();
+    let weaks: Vec<_> = strongs.iter().map(Strong::alias).collect();
+    drop(strongs.remove(1));
+    assert!(read_all(&weaks, InvalidPolicy::Fail).is_none());
+    let readings = read_all(&weaks, InvalidPolicy::Skip).unwrap();
+    assert_eq!(readings.iter().map(|r| **r).collect::<Vec<_>>(), [0, 2]);
+    drop(readings);
+    assert_eq!(write_all(&weaks, InvalidPolicy::Skip).unwrap().len(), 2);
+}
+
+#[test]
+fn lock_all_locks_every_reference_or_none()
+{
+    let count = Strong::new(1);
+    let name = Strong::new(String::from("a"));
+    let alias = name.alias();
+    {
+        let (count, mut name) = lock_all!(read count, write alias).unwrap();
+        name.push_str(&count.to_string());
+    }
+    assert_eq!(*name.try_read().unwrap(), "a1");
+    assert!(lock_all!(read count, write name, write alias).is_none());
+    assert!(count.try_write().is_some());
+    assert!(name.try_write().is_some());
+    let (first, second) = lock_all!(read name, read alias).unwrap();
+    assert_eq!(*first, *second);
+}
+
+#[test]
+fn value_helpers_swap_the_referent_in_place()
+{
+    let strong = Strong::new(String::from("a"));
+    let mut writing = strong.write();
+    assert_eq!(writing.replace(String::from("b")), "a");
+    assert_eq!(*writing, "b");
+    writing.set(String::from("c"));
+    assert_eq!(writing.take(), "c");
+    assert!(writing.is_empty());
+    drop(writing);
+    assert_eq!(*strong.read(), "");
+}
+
+#[test]
+fn clone_inner_copies_into_an_independent_allocation()
+{
+    let strong = Strong::new(vec![1]);
+    let clone = strong.clone_inner().unwrap();
+    assert_ne!(clone.id(), strong.id());
+    assert!(!clone.is_aliased_by(&strong.alias()));
+    clone.write().push(2);
+    assert_eq!(*strong.read(), [1]);
+    let weak = strong.alias();
+    let writing = weak.try_write().unwrap();
+    assert!(strong.clone_inner().is_none());
+    drop(writing);
+}
+
+#[test]
+fn make_mut_clones_away_from_readers()
+{
+    let mut strong = Strong::new(vec![1]);
+    let id = strong.id();
+    strong.make_mut().unwrap().push(2);
+    assert_eq!(strong.id(), id);
+    let weak = strong.alias();
+    let reading = weak.try_read().unwrap();
+    strong.make_mut().unwrap().push(3);
+    assert_ne!(strong.id(), id);
+    assert_eq!(*reading, [1, 2]);
+    assert!(weak.is_valid());
+    drop(reading);
+    assert!(!weak.is_valid());
+    assert_eq!(*strong.read(), [1, 2, 3]);
+    let weak = strong.alias();
+    let writing = weak.try_write().unwrap();
+    assert!(strong.make_mut().is_none());
+    drop(writing);
+}
+
+#[test]
+fn owned_guards_outlive_the_reference_they_were_locked_through()
+{
+    let strong = Strong::new(1);
+    let reading: OwnedReading<i32> = strong.alias().try_read().unwrap().into_owned();
+    assert!(strong.try_write().is_none());
+    assert_eq!(*reading, 1);
+    drop(reading);
+    let mut writing: OwnedWriting<i32> = strong.alias().try_write().unwrap().into_owned();
+    *writing = 2;
+    assert!(strong.try_read().is_none());
+    drop(writing);
+    assert_eq!(*strong.read(), 2);
+}
+
+#[test]
+fn gen_refs_hold_either_kind_of_reference()
+{
+    let (count, drops) = counter();
+    let strong = GenRef::from(Strong::new(Dropped(count)));
+    let weak = GenRef::from(strong.alias());
+    assert!(strong.is_strong());
+    assert!(!weak.is_strong());
+    assert!(weak.try_read().is_some());
+    assert!(weak.alias().ptr_eq(&strong.alias()));
+    let GenRefEnum::Weak(weak) = weak.into_enum() else {
+        panic!("weak reference turned strong")
+    };
+    let GenRefEnum::Strong(owner) = strong.into_enum() else {
+        panic!("strong reference turned weak")
+    };
+    let strong = GenRef::from(GenRefEnum::Strong(owner));
+    drop(GenRef::from(weak.clone()));
+    assert_eq!(drops(), 0);
+    drop(strong);
+    assert_eq!(drops(), 1);
+    assert!(!weak.is_valid());
+}
+
+#[test]
+fn sendable_moves_ownership_to_another_thread()
+{
+    let (count, drops) = counter();
+    let strong = Strong::new(Dropped(count));
+    let weak = strong.alias();
+    let sendable = strong.send();
+    assert!(weak.is_valid());
+    thread::spawn(move || {
+        let strong = sendable.into_inner();
+        assert!(strong.try_write().is_some());
+    })
+    .join()
+    .unwrap();
+    assert_eq!(drops(), 1);
+    assert!(!weak.is_valid());
+}
+
+#[test]
+#[should_panic(expected = "cannot globalize a borrowed thread-local reference")]
+fn sending_a_borrowed_referent_panics()
+{
+    let strong = Strong::new(1);
+    let weak = strong.alias();
+    let _reading = weak.try_read().unwrap();
+    let _ = strong.send();
+}
+
+#[test]
+fn shareable_aliases_lock_across_threads()
+{
+    let strong = Strong::new(0);
+    let shared = strong.alias().share();
+    let writers: Vec<_> = (0..4)
+        .map(|_| {
+            let shared = shared.clone();
+            thread::spawn(move || {
+                for _ in 0..100 {
+                    *shared.write().unwrap() += 1;
+                }
+            })
+        })
+        .collect();
+    for writer in writers {
+        writer.join().unwrap();
+    }
+    assert_eq!(*strong.read(), 400);
+    drop(strong);
+    let stale = shared.clone();
+    assert!(thread::spawn(move || !stale.is_valid()).join().unwrap());
+    assert!(!shared.into_inner().is_valid());
+}
+
+#[test]
+fn transferrables_carry_either_kind_across_threads()
+{
+    let (count, drops) = counter();
+    let strong = Strong::new(Dropped(count));
+    let weak = strong.alias();
+    let owner = GenRef::from(strong).transfer();
+    let alias = GenRef::from(weak.clone()).transfer();
+    let TransferrableEnum::Shareable(alias) = alias.into_enum() else {
+        panic!("weak reference transferred as strong")
+    };
+    let owner = Transferrable::from(owner.into_enum());
+    thread::spawn(move || {
+        let owner = owner.into_inner();
+        assert!(owner.is_strong());
+        assert!(alias.try_read().is_some());
+    })
+    .join()
+    .unwrap();
+    assert_eq!(drops(), 1);
+    assert!(!weak.is_valid());
+}
+
+#[test]
+fn gen_ref_like_abstracts_over_reference_kinds()
+{
+    fn bump<R: GenRefLike<i32>>(reference: &R) -> Option<i32>
+    {
+        let mut writing = reference.try_write()?;
+        *writing += 1;
+        Some(*writing)
+    }
+
+    let strong = Strong::new(0);
+    let weak = strong.alias();
+    let gen_ref = GenRef::from(strong.alias());
+    assert_eq!(bump(&strong), Some(1));
+    assert_eq!(bump(&weak), Some(2));
+    assert_eq!(bump(&gen_ref), Some(3));
+    assert_eq!(GenRefLike::generation(&weak), strong.generation());
+    assert_eq!(GenRefLike::generation(&gen_ref), strong.generation());
+    assert!(GenRefLike::alias(&gen_ref).ptr_eq(&weak));
+    let reading = GenRefLike::try_read(&weak).unwrap();
+    assert_eq!(bump(&strong), None);
+    drop(reading);
+    drop(strong);
+    assert_eq!(bump(&weak), None);
+}
+
+#[test]
+fn is_aliased_by_accepts_unsized_projections()
+{
+    let strong = Strong::new(String::from("abc"));
+    assert!(strong.is_aliased_by(&strong.alias_of(String::as_str)));
+    let slice = Strong::from_vec(vec![1, 2]);
+    assert!(slice.is_aliased_by(&slice.alias_range(0..1)));
+    assert!(!slice.is_aliased_by(&strong.alias_of(String::as_str)));
+}
+
+#[test]
+fn weak_projections_share_the_source_counter()
+{
+    let strong = Strong::new((1, String::from("b")));
+    let weak = strong.alias();
+    let name = weak.alias_of(|pair| pair.1.as_str()).unwrap();
+    assert_eq!(&*name.try_read().unwrap(), "b");
+    assert!(strong.is_aliased_by(&name));
+    let writing = weak.try_write().unwrap();
+    assert!(weak.alias_of(|pair| &pair.0).is_none());
+    drop(writing);
+    drop(strong);
+    assert!(!name.is_valid());
+    assert!(weak.alias_of(|pair| &pair.0).is_none());
+}
+
+#[test]
+fn shared_projections_leave_the_owners_drop_on_its_thread()
+{
+    struct NotSend(
+        Arc<parking_lot::Mutex<Option<thread::ThreadId>>>,
+        PhantomData<*const ()>,
+    );
+
+    impl Drop for NotSend
+    {
+        fn drop(&mut self) { *self.0.lock() = Some(thread::current().id()) }
+    }
+
+    let dropped_on = Arc::new(parking_lot::Mutex::new(None));
+    let strong = Strong::new((NotSend(dropped_on.clone(), PhantomData), 7));
+    let shared = strong.alias_of(|pair| &pair.1).share();
+    let (locked, wait_locked) = std::sync::mpsc::channel();
+    let (release, wait_release) = std::sync::mpsc::channel();
+    let reader = thread::spawn(move || {
+        let reading = shared.try_read().unwrap();
+        locked.send(*reading).unwrap();
+        wait_release.recv().unwrap();
+        drop(reading);
+        counter_efficiency()
+    });
+    assert_eq!(wait_locked.recv().unwrap(), 7);
+    drop(strong);
+    release.send(()).unwrap();
+    assert_eq!(reader.join().unwrap().free_listed, 0);
+    assert_eq!(*dropped_on.lock(), None);
+    sweep();
+    assert_eq!(*dropped_on.lock(), Some(thread::current().id()));
+}
+
+#[test]
+fn stale_subscriptions_leave_a_reused_accounts_callbacks_alone()
+{
+    let first = Strong::new(1);
+    let stale = first.alias();
+    drop(first);
+    let second = Strong::new(2);
+    assert_eq!(stale.0.account().address(), second.0.account().address());
+    let (count, fired) = counter();
+    second.alias().on_invalidate(move || {
+        count.fetch_add(1, SeqCst);
+    });
+    let (stale_count, stale_fired) = counter();
+    stale.on_invalidate(move || {
+        stale_count.fetch_add(1, SeqCst);
+    });
+    assert_eq!((stale_fired(), fired()), (1, 0));
+    drop(second);
+    assert_eq!(fired(), 1);
+}
+
+#[test]
+fn is_aliased_by_sees_through_sharing()
+{
+    let strong = Strong::new((1, 2));
+    let local = strong.alias();
+    let shared = strong.alias_of(|pair| &pair.1).share();
+    assert!(strong.is_aliased_by(&local));
+    assert!(strong.is_aliased_by(&shared));
+    assert!(!Strong::new((1, 2)).is_aliased_by(&shared));
+}
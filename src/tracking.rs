@@ -1,4 +1,15 @@
-use crate::{global_ledger, local_ledger};
+use lazy_static::lazy_static;
+use std::{
+    any::TypeId,
+    cell::RefCell,
+    collections::HashMap,
+    mem,
+    ptr::NonNull,
+    sync::atomic::{fence, AtomicU64, AtomicUsize, Ordering},
+    time::Instant,
+};
+
+use crate::{global_ledger, local_ledger, testing};
 
 use super::global_ledger::GlobalIndex;
 
@@ -11,11 +22,29 @@ pub(crate) trait Tracking
     fn try_lock_exclusive(&self) -> bool;
     fn lock_exclusive(&self);
     fn try_lock_shared(&self) -> bool;
+    fn lock_shared(&self);
+    fn try_lock_exclusive_until(&self, deadline: Instant) -> bool;
+    fn try_lock_shared_until(&self, deadline: Instant) -> bool;
     fn try_upgrade(&self) -> bool;
+    unsafe fn downgrade(&self);
     unsafe fn unlock_exclusive(&self);
     unsafe fn unlock_shared(&self);
+    fn flags(&self) -> u64;
+    fn raise_flags(&self, flags: u64);
+    fn allocation_id(&self) -> u64;
+    fn set_allocation_id(&self, id: u64);
+    /// bumps the publication count with release ordering, returning it
+    fn publish(&self) -> u64;
+    /// the publication count, loaded with acquire ordering
+    fn published(&self) -> u64;
 }
 
+pub(crate) const FROZEN: u64 = 0b0001u64.reverse_bits();
+pub(crate) const ALIASED: u64 = 0b0010u64.reverse_bits();
+pub(crate) const PINNED: u64 = 0b0100u64.reverse_bits();
+/// the owner's referent is `Send`, so its deferred drop may run on any thread
+pub(crate) const SENDABLE: u64 = 0b1000u64.reverse_bits();
+
 #[derive(Clone, Copy)]
 pub(crate) union Account
 {
@@ -30,6 +59,33 @@ pub(crate) enum AccountEnum
     Global(GlobalIndex),
 }
 
+impl AccountEnum
+{
+    pub(crate) fn address(&self) -> usize
+    {
+        match self {
+            Self::Local(l) => l.address(),
+            Self::Global(g) => g.address(),
+        }
+    }
+
+    /// the global account behind a sharable thread-local one, so that other
+    /// threads never have to touch this thread's ledger
+    pub(crate) fn resolved(self) -> Self
+    {
+        match self {
+            Self::Local(l) => l.global().map_or(self, Self::Global),
+            Self::Global(_) => self,
+        }
+    }
+
+    /// never globalized, so only this thread can ever hold its lock
+    pub(crate) fn is_thread_local(&self) -> bool
+    {
+        matches!(self, Self::Local(l) if !l.is_sharable())
+    }
+}
+
 impl Tracking for AccountEnum
 {
     fn generation(&self) -> u64
@@ -72,6 +128,30 @@ impl Tracking for AccountEnum
         }
     }
 
+    fn lock_shared(&self)
+    {
+        match self {
+            Self::Local(l) => l.lock_shared(),
+            Self::Global(g) => g.lock_shared(),
+        }
+    }
+
+    fn try_lock_exclusive_until(&self, deadline: Instant) -> bool
+    {
+        match self {
+            Self::Local(l) => l.try_lock_exclusive_until(deadline),
+            Self::Global(g) => g.try_lock_exclusive_until(deadline),
+        }
+    }
+
+    fn try_lock_shared_until(&self, deadline: Instant) -> bool
+    {
+        match self {
+            Self::Local(l) => l.try_lock_shared_until(deadline),
+            Self::Global(g) => g.try_lock_shared_until(deadline),
+        }
+    }
+
     fn try_upgrade(&self) -> bool
     {
         match self {
@@ -80,6 +160,14 @@ impl Tracking for AccountEnum
         }
     }
 
+    unsafe fn downgrade(&self)
+    {
+        match self {
+            Self::Local(l) => l.downgrade(),
+            Self::Global(g) => g.downgrade(),
+        }
+    }
+
     unsafe fn unlock_exclusive(&self)
     {
         match self {
@@ -95,12 +183,191 @@ impl Tracking for AccountEnum
             Self::Global(g) => g.unlock_shared(),
         }
     }
+
+    fn flags(&self) -> u64
+    {
+        match self {
+            Self::Local(l) => l.flags(),
+            Self::Global(g) => g.flags(),
+        }
+    }
+
+    fn raise_flags(&self, flags: u64)
+    {
+        match self {
+            Self::Local(l) => l.raise_flags(flags),
+            Self::Global(g) => g.raise_flags(flags),
+        }
+    }
+
+    fn allocation_id(&self) -> u64
+    {
+        match self {
+            Self::Local(l) => l.allocation_id(),
+            Self::Global(g) => g.allocation_id(),
+        }
+    }
+
+    fn set_allocation_id(&self, id: u64)
+    {
+        match self {
+            Self::Local(l) => l.set_allocation_id(id),
+            Self::Global(g) => g.set_allocation_id(id),
+        }
+    }
+
+    fn publish(&self) -> u64
+    {
+        match self {
+            Self::Local(l) => l.publish(),
+            Self::Global(g) => g.publish(),
+        }
+    }
+
+    fn published(&self) -> u64
+    {
+        match self {
+            Self::Local(l) => l.published(),
+            Self::Global(g) => g.published(),
+        }
+    }
 }
 
+static NEXT_ALLOCATION_ID: AtomicU64 = AtomicU64::new(1);
+
+pub(crate) fn next_allocation_id() -> u64 { NEXT_ALLOCATION_ID.fetch_add(1, Ordering::Relaxed) }
+
 pub(crate) unsafe fn free(ac: AccountEnum)
 {
+    let id = ac.allocation_id();
     match ac {
         AccountEnum::Local(l) => local_ledger::free(l),
         AccountEnum::Global(g) => global_ledger::free(g),
     }
+    testing::freed(id);
+    notify(id);
+}
+
+thread_local! {
+    static HELD_EXCLUSIVE : RefCell<Vec<u64>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Records an exclusive lock by allocation id, or returns the held key it
+/// would be out of order with.
+pub(crate) fn hold_exclusive(id: u64) -> Result<(), u64>
+{
+    HELD_EXCLUSIVE.with_borrow_mut(|held| match held.iter().copied().max() {
+        Some(max) if max >= id => Err(max),
+        _ => {
+            held.push(id);
+            Ok(())
+        }
+    })
+}
+
+pub(crate) fn release_exclusive(id: u64)
+{
+    HELD_EXCLUSIVE.with_borrow_mut(|held| {
+        if let Some(i) = held.iter().rposition(|&h| h == id) {
+            held.swap_remove(i);
+        }
+    })
+}
+
+pub(crate) type Callback = Box<dyn FnOnce() + Send>;
+
+lazy_static! {
+    static ref SUBSCRIBERS: parking_lot::Mutex<HashMap<u64, Vec<Callback>>> =
+        parking_lot::Mutex::new(HashMap::new());
+}
+
+// callbacks registered and not yet run, so that frees can skip the registry
+static SUBSCRIBED: AtomicUsize = AtomicUsize::new(0);
+
+/// Registers `callback` to run on whichever thread invalidates the account's
+/// `generation`, keyed by allocation id as that survives globalization. Hands
+/// the callback back if the generation is already gone, leaving every other
+/// subscription alone: a stale reference may be looking at an account that
+/// has since been reused for another allocation.
+pub(crate) fn subscribe(
+    ac: AccountEnum, generation: u64, callback: Callback,
+) -> Result<(), Callback>
+{
+    let id = ac.allocation_id();
+    let mut subscribers = SUBSCRIBERS.lock();
+    subscribers.entry(id).or_default().push(callback);
+    SUBSCRIBED.fetch_add(1, Ordering::SeqCst);
+    // pairs with the fence in notify, so either this sees the invalidation or
+    // the freeing thread sees the subscription
+    fence(Ordering::SeqCst);
+    if ac.generation() == generation {
+        return Ok(());
+    }
+    // the registry stayed locked, so the last callback is still this one
+    let callbacks = subscribers.get_mut(&id).expect("subscription vanished");
+    let callback = callbacks.pop().expect("subscription vanished");
+    if callbacks.is_empty() {
+        subscribers.remove(&id);
+    }
+    SUBSCRIBED.fetch_sub(1, Ordering::SeqCst);
+    Err(callback)
+}
+
+fn notify(id: u64)
+{
+    fence(Ordering::SeqCst);
+    if SUBSCRIBED.load(Ordering::SeqCst) == 0 {
+        return;
+    }
+    let callbacks = SUBSCRIBERS.lock().remove(&id).unwrap_or_default();
+    SUBSCRIBED.fetch_sub(callbacks.len(), Ordering::SeqCst);
+    for callback in callbacks {
+        callback()
+    }
+}
+
+// referent type plus the pointer's address and metadata, so that projections
+// sharing the base address, such as a leading subslice, don't match
+type ReleasedPointer = (TypeId, [usize; 2]);
+
+lazy_static! {
+    static ref RELEASED: parking_lot::Mutex<HashMap<u64, ReleasedPointer>> =
+        parking_lot::Mutex::new(HashMap::new());
+}
+
+fn released_pointer<T: ?Sized + 'static>(pointer: NonNull<T>) -> ReleasedPointer
+{
+    let mut words = [0usize; 2];
+    let size = mem::size_of::<NonNull<T>>();
+    assert!(
+        size <= mem::size_of_val(&words),
+        "pointer wider than two words"
+    );
+    unsafe {
+        std::ptr::copy_nonoverlapping(
+            (&pointer as *const NonNull<T>).cast::<u8>(),
+            words.as_mut_ptr().cast::<u8>(),
+            size,
+        )
+    };
+    (TypeId::of::<T>(), words)
+}
+
+/// Parks ownership of an allocation, identified by its allocation id and the
+/// full pointer to its referent.
+pub(crate) fn release<T: ?Sized + 'static>(id: u64, pointer: NonNull<T>)
+{
+    RELEASED.lock().insert(id, released_pointer(pointer));
+}
+
+/// Takes parked ownership back, if it was parked with exactly this pointer.
+pub(crate) fn reclaim<T: ?Sized + 'static>(id: u64, pointer: NonNull<T>) -> bool
+{
+    let mut released = RELEASED.lock();
+    if released.get(&id) == Some(&released_pointer(pointer)) {
+        released.remove(&id);
+        true
+    } else {
+        false
+    }
 }
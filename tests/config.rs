@@ -0,0 +1,97 @@
+//! The configuration is process-wide, so tests toggling it live in their own
+//! binary and take turns.
+
+use genref::*;
+
+static CONFIG: parking_lot::Mutex<()> = parking_lot::Mutex::new(());
+
+#[test]
+fn reentrant_exclusive_borrows_fail_quietly_by_default()
+{
+    let _config = CONFIG.lock();
+    let strong = Strong::new(5);
+    let weak = strong.alias();
+    let _writing = weak.try_write().unwrap();
+    assert!(weak.try_write().is_none());
+    assert_eq!(strong.compare_and_swap(&5, 6), Err(6));
+}
+
+#[test]
+fn reentrant_exclusive_borrows_panic_when_enabled()
+{
+    let _config = CONFIG.lock();
+    let strong = Strong::new(5);
+    let weak = strong.alias();
+    let _writing = weak.try_write().unwrap();
+    config::reentrant_panic(true);
+    let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| weak.try_write()));
+    config::reentrant_panic(false);
+    assert_eq!(res.is_err(), cfg!(debug_assertions));
+}
+
+#[test]
+fn lock_order_violations_panic_when_enabled()
+{
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    let _config = CONFIG.lock();
+    let (a, b) = (Strong::new(1), Strong::new(2));
+    let (first, second) = if a.lock_order_key() < b.lock_order_key() {
+        (a.alias(), b.alias())
+    } else {
+        (b.alias(), a.alias())
+    };
+    config::lock_order_check(true);
+    let in_order = catch_unwind(AssertUnwindSafe(|| {
+        let _first = first.try_write().unwrap();
+        let _second = second.try_write().unwrap();
+    }));
+    let out_of_order = catch_unwind(AssertUnwindSafe(|| {
+        let _second = second.try_write().unwrap();
+        let _first = first.try_write();
+    }));
+    config::lock_order_check(false);
+    assert!(in_order.is_ok());
+    assert_eq!(out_of_order.is_err(), cfg!(debug_assertions));
+    // the violating lock was released before panicking
+    assert!(first.try_write().is_some());
+}
+
+#[test]
+fn transaction_writes_are_subject_to_the_lock_order_check()
+{
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    let _config = CONFIG.lock();
+    let (a, b) = (Strong::new(1), Strong::new(2));
+    let (first, second) = if a.lock_order_key() < b.lock_order_key() {
+        (&a, &b)
+    } else {
+        (&b, &a)
+    };
+    config::lock_order_check(true);
+    let in_order = catch_unwind(AssertUnwindSafe(|| {
+        transaction(|tx| {
+            *tx.write(first)? += 10;
+            *tx.write(second)? += 10;
+            Some(())
+        })
+    }));
+    let out_of_order = catch_unwind(AssertUnwindSafe(|| {
+        transaction(|tx| {
+            tx.write(second)?;
+            tx.write(first)?;
+            Some(())
+        })
+    }));
+    // the transaction released what it recorded, so guards still line up
+    let after = catch_unwind(AssertUnwindSafe(|| {
+        let _first = first.try_write().unwrap();
+        let _second = second.try_write().unwrap();
+    }));
+    config::lock_order_check(false);
+    assert_eq!(in_order.unwrap(), Some(()));
+    assert_eq!(out_of_order.is_err(), cfg!(debug_assertions));
+    assert!(after.is_ok());
+    assert_eq!((*a.try_read().unwrap(), *b.try_read().unwrap()), (11, 12));
+}